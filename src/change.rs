@@ -0,0 +1,102 @@
+pub mod settings;
+pub mod timed;
+
+use crate::change::settings::Settings;
+use crate::cpu;
+use crate::cpu::report::ChangeResult;
+use crate::cpu::Outcomes;
+use crate::journal;
+use crate::sys_linux::{drop_privileges, restore_privileges};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+/// Applies a change to the state of the cores with root privileges and records it in the journal.
+/// # Arguments
+/// * `action` - Description of the change for the journal, e.g. `set 2`.
+/// * `undo_of` - The ID of the journal entry reverted by this change, if any.
+/// * `change` - The function that changes the state and returns the number of cores updated.
+pub fn apply<F>(action: &str, undo_of: Option<usize>, change: F) -> Result<usize, Box<dyn Error>>
+where
+    F: FnOnce() -> Result<usize, Box<dyn Error>>,
+{
+    let state_before = cpu::get_state()?;
+
+    restore_privileges(); // get root access to change the state and write the journal
+    let result = change();
+    if result.is_ok() {
+        match cpu::get_state() {
+            Ok(state_after) => journal::record(action, undo_of, &state_before, &state_after),
+            Err(e) => eprintln!("Could not record the operation in the journal. {e}"),
+        }
+    }
+    drop_privileges(); // drop root access
+
+    result
+}
+
+/// Applies a change to settings of the cores other than their online state (e.g. isolation or
+/// cpufreq) with root privileges and records the settings before and after it in the journal.
+/// # Arguments
+/// * `action` - Description of the change for the journal, e.g. `isolate 2-3`.
+/// * `undo_of` - The ID of the journal entry reverted by this change, if any.
+/// * `read` - The function that reads the settings the change affects, e.g. `Settings::isolated`.
+/// * `change` - The function that changes the settings and returns the number of items updated.
+pub fn apply_settings<R, F>(action: &str, undo_of: Option<usize>, read: R, change: F) -> Result<usize, Box<dyn Error>>
+where
+    R: Fn() -> Settings,
+    F: FnOnce() -> Result<usize, Box<dyn Error>>,
+{
+    restore_privileges(); // get root access to change the settings and write the journal
+    let before = read();
+    let result = change();
+    if result.is_ok() {
+        match cpu::get_state() {
+            Ok(state) => journal::record_settings(action, undo_of, &state, before, read()),
+            Err(e) => eprintln!("Could not record the operation in the journal. {e}"),
+        }
+    }
+    drop_privileges(); // drop root access
+
+    result
+}
+
+/// Applies a change like `apply()`, keeping track of what happened to each core.
+/// # Arguments
+/// * `action` - Description of the change for the journal, e.g. `set 2`.
+/// * `undo_of` - The ID of the journal entry reverted by this change, if any.
+/// * `change` - The function that changes the state and returns the transitions of the cores and
+///   the moved IRQs, e.g. `cpu::set_cores_with_outcomes()`.
+pub fn apply_transitions<F>(action: &str, undo_of: Option<usize>, change: F) -> Result<ChangeResult, Box<dyn Error>>
+where
+    F: FnOnce() -> Result<Outcomes, Box<dyn Error>>,
+{
+    let state_before = cpu::get_state()?;
+    let mut outcomes = Outcomes::default();
+    apply(action, undo_of, || {
+        outcomes = change()?;
+        Ok(cpu::count_changed(&outcomes.transitions))
+    })?;
+    Ok(ChangeResult::new(action, outcomes, &state_before, &cpu::get_state()?))
+}
+
+/// Creates a HashMap with the needed state for each core: the cores in `cores_online` are set
+/// online, all the other cores are set offline.
+pub fn core_states_from(cores_online: &HashSet<usize>, total_cores: usize) -> HashMap<usize, bool> {
+    (0..total_cores)
+        .map(|core| (core, cores_online.contains(&core)))
+        .collect()
+}
+
+/// Brings the cores back to a previous state, e.g. a snapshot taken with `cpu::get_state()`.
+/// The revert is recorded as the undo of the changes recorded since the snapshot, so that
+/// `cores undo` doesn't bring back the temporary state but reverts the change before it.
+/// # Arguments
+/// * `cores_online` - The cores that were online in the previous state, all the others are set offline.
+/// * `action` - Description of the change for the journal.
+/// * `since` - The ID of the last journal entry when the snapshot was taken, see `journal::last_id()`.
+/// # Returns
+/// The number of cores updated.
+pub fn restore(cores_online: &HashSet<usize>, action: &str, since: usize) -> Result<usize, Box<dyn Error>> {
+    let core_states = core_states_from(cores_online, cpu::get_state()?.total_cores);
+    apply(action, Some(since + 1), || cpu::set_cores(&core_states))
+}
@@ -0,0 +1,175 @@
+use crate::core_list;
+use crate::cpu::cpuset::partition;
+use crate::cpu::freq::{self, FreqSettings};
+use crate::cpu::{hotplug, idle, pstate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+
+/// Settings of the cores other than their online state, as recorded in the journal before and
+/// after `cores isolate`, `unisolate`, `freq`, `epp`, `pstate`, `idle` and `hotplug fail`, so that
+/// `cores undo` can put them back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Settings {
+    /// The cores in the isolated partition of `cores isolate`, in CPU list format.
+    Isolated { cores: String },
+    /// The governor and the frequency limits of the cores with a cpufreq policy, and boost.
+    Freq { cores: BTreeMap<usize, FreqSettings>, boost: Option<bool> },
+    /// The energy performance preference of the cores that have one.
+    Epp { cores: BTreeMap<usize, String> },
+    /// The mode of the P-state driver, `None` without `intel_pstate` or `amd-pstate`.
+    Pstate { mode: Option<String> },
+    /// Whether each idle state of the cores with idle states is disabled, by state index.
+    Idle { cores: BTreeMap<usize, Vec<bool>> },
+    /// The state at which the next hotplug operation of a core fails, if any.
+    HotplugFail { core: usize, fail: Option<i32> },
+}
+
+impl Settings {
+    /// Reads the cores isolated by `cores isolate`.
+    pub fn isolated() -> Settings {
+        Settings::Isolated { cores: core_list::format(partition::partition_cores()) }
+    }
+
+    /// Reads the cpufreq settings of the `total_cores` cores.
+    pub fn freq(total_cores: usize) -> Settings {
+        let cores = freq::read_all(total_cores)
+            .into_iter()
+            .map(|(core, core_freq)| {
+                let settings = FreqSettings {
+                    governor: core_freq.governor,
+                    min_khz: core_freq.min_khz,
+                    max_khz: core_freq.max_khz,
+                };
+                (core, settings)
+            })
+            .collect();
+        Settings::Freq { cores, boost: freq::get_boost() }
+    }
+
+    /// Reads the energy performance preference of the `total_cores` cores.
+    pub fn epp(total_cores: usize) -> Settings {
+        let cores = freq::read_all(total_cores)
+            .into_iter()
+            .filter_map(|(core, core_freq)| Some((core, core_freq.epp?)))
+            .collect();
+        Settings::Epp { cores }
+    }
+
+    /// Reads the mode of the P-state driver.
+    pub fn pstate() -> Settings {
+        Settings::Pstate { mode: pstate::status().map(|status| status.status) }
+    }
+
+    /// Reads which idle states of the `total_cores` cores are disabled.
+    pub fn idle(total_cores: usize) -> Settings {
+        let cores = idle::read_all(total_cores)
+            .into_iter()
+            .map(|core_idle| (core_idle.core, core_idle.states.iter().map(|state| state.disabled).collect()))
+            .collect();
+        Settings::Idle { cores }
+    }
+
+    /// Reads the hotplug failure injected into `core`.
+    pub fn hotplug_fail(core: usize) -> Settings {
+        let fail = hotplug::get_cores()
+            .ok()
+            .and_then(|cores| cores.into_iter().find(|core_hotplug| core_hotplug.core == core))
+            .and_then(|core_hotplug| core_hotplug.fail);
+        Settings::HotplugFail { core, fail }
+    }
+
+    /// Reads the current value of the same settings.
+    /// # Arguments
+    /// * `total_cores` - The number of cores, see `CPUState`.
+    pub fn current(&self, total_cores: usize) -> Settings {
+        match self {
+            Settings::Isolated { .. } => Settings::isolated(),
+            Settings::Freq { .. } => Settings::freq(total_cores),
+            Settings::Epp { .. } => Settings::epp(total_cores),
+            Settings::Pstate { .. } => Settings::pstate(),
+            Settings::Idle { .. } => Settings::idle(total_cores),
+            Settings::HotplugFail { core, .. } => Settings::hotplug_fail(*core),
+        }
+    }
+
+    /// Puts the settings back, e.g. as they were before a change. Settings of cores that are
+    /// offline now are skipped. Requires root privileges.
+    /// # Arguments
+    /// * `total_cores` - The number of cores, see `CPUState`.
+    /// # Returns
+    /// The number of cores whose settings changed, the number of idle states for `Idle`.
+    pub fn restore(&self, total_cores: usize) -> Result<usize, Box<dyn Error>> {
+        let mut updated = 0;
+        match (self, self.current(total_cores)) {
+            (Settings::Isolated { cores }, Settings::Isolated { cores: current }) => {
+                let (cores, current) = (core_list::parse_or_empty(cores), core_list::parse_or_empty(&current));
+                let returned: HashSet<usize> = current.difference(&cores).copied().collect();
+                if !returned.is_empty() {
+                    updated += partition::unisolate(Some(&returned))?;
+                }
+                let isolated: HashSet<usize> = cores.difference(&current).copied().collect();
+                if !isolated.is_empty() {
+                    updated += partition::isolate(&isolated)?;
+                }
+            }
+            (Settings::Freq { cores, boost }, Settings::Freq { cores: current, boost: current_boost }) => {
+                for (core, settings) in cores {
+                    if current.get(core).is_some_and(|current| current != settings) {
+                        updated += freq::set(&HashSet::from([*core]), settings)?;
+                    }
+                }
+                if let (Some(is_enabled), Some(current_boost)) = (boost, current_boost) {
+                    if *is_enabled != current_boost {
+                        freq::set_boost(*is_enabled)?;
+                    }
+                }
+            }
+            (Settings::Epp { cores }, Settings::Epp { cores: current }) => {
+                for (core, epp) in cores {
+                    if current.get(core).is_some_and(|current| current != epp) {
+                        updated += pstate::set_epp(&HashSet::from([*core]), epp)?;
+                    }
+                }
+            }
+            (Settings::Pstate { mode: Some(mode) }, Settings::Pstate { .. }) => {
+                updated += pstate::set_status(mode)?;
+            }
+            (Settings::Idle { cores }, Settings::Idle { cores: current }) => {
+                for (core, disabled) in cores {
+                    let Some(current) = current.get(core) else {
+                        continue; // the core is offline
+                    };
+                    for (index, (disabled, current)) in disabled.iter().zip(current).enumerate() {
+                        if disabled != current {
+                            idle::write_disable(*core, index, *disabled)?;
+                            updated += 1;
+                        }
+                    }
+                }
+            }
+            (Settings::HotplugFail { core, fail }, Settings::HotplugFail { fail: current, .. }) if *fail != current => {
+                hotplug::set_fail(*core, *fail)?;
+                updated += 1;
+            }
+            _ => {}
+        }
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let settings = Settings::Freq {
+            cores: BTreeMap::from([(2, FreqSettings { governor: Some("powersave".to_string()), min_khz: None, max_khz: Some(1_800_000) })]),
+            boost: Some(true),
+        };
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(serde_json::from_str::<Settings>(&json).unwrap(), settings);
+    }
+}
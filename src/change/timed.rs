@@ -0,0 +1,135 @@
+use crate::change;
+use crate::core_list;
+use crate::cpu::cpu_state::CPUState;
+use crate::duration;
+use crate::sys_linux::{catch_interrupts, interrupt_signal, start_new_session};
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check for interrupts while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps until `deadline` or until `cores` is interrupted, whichever comes first.
+fn wait_until(deadline: Instant) {
+    while interrupt_signal().is_none() {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn revert(cores_online: &HashSet<usize>, reason: &str, since: usize) -> Result<usize, Box<dyn Error>> {
+    let action = format!("revert to {} ({})", core_list::format(cores_online.iter().copied()), reason);
+    change::restore(cores_online, &action, since)
+}
+
+/// Keeps `cores` in the foreground for `duration` and then restores the state taken before the change.
+/// Ctrl-C (or SIGTERM/SIGHUP) restores the state right away. Requires `catch_interrupts()` to have
+/// been called before the change, so that an interrupt during the change restores the state too.
+/// # Arguments
+/// * `duration` - How long to keep the change.
+/// * `state_before` - The state taken before the change.
+/// * `since` - The ID of the last journal entry before the change, see `journal::last_id()`.
+/// # Returns
+/// The number of cores updated by restoring the state.
+pub fn revert_after(duration: Duration, state_before: &CPUState, since: usize) -> Result<usize, Box<dyn Error>> {
+    eprintln!(
+        "Restoring the previous state (online: {}) in {}. Press Ctrl-C to restore it now.",
+        state_before.online_core_list(),
+        duration::format(duration)
+    );
+    wait_until(Instant::now() + duration);
+
+    let cores_online = state_before.online_cores().into_iter().collect();
+    revert(&cores_online, &format!("--for {}", duration::format(duration)), since)
+}
+
+/// Asks the user to confirm the change and restores the state taken before the change unless
+/// the user confirms within `duration`. Ctrl-C (or SIGTERM/SIGHUP) restores the state right away.
+/// Requires `catch_interrupts()` to have been called before the change, like `revert_after()`.
+/// # Returns
+/// `None` if the change was confirmed, otherwise the number of cores updated by restoring the state.
+pub fn revert_unless_confirmed(
+    duration: Duration,
+    state_before: &CPUState,
+    since: usize,
+) -> Result<Option<usize>, Box<dyn Error>> {
+    eprint!(
+        "Keep the new state? The previous state (online: {}) is restored in {} unless confirmed [y/N]: ",
+        state_before.online_core_list(),
+        duration::format(duration)
+    );
+    io::stderr().flush()?;
+
+    // Read the answer in a separate thread, so that waiting for it can time out.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).is_ok() {
+            let _ = sender.send(answer);
+        }
+    });
+
+    let deadline = Instant::now() + duration;
+    let confirmed = loop {
+        if interrupt_signal().is_some() {
+            break false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break false;
+        }
+        match receiver.recv_timeout(POLL_INTERVAL.min(deadline - now)) {
+            Ok(answer) => break matches!(answer.trim(), "y" | "Y" | "yes"),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break false,
+        }
+    };
+    eprintln!();
+
+    if confirmed {
+        return Ok(None);
+    }
+    let cores_online = state_before.online_cores().into_iter().collect();
+    let reason = format!("not confirmed within {}", duration::format(duration));
+    Ok(Some(revert(&cores_online, &reason, since)?))
+}
+
+/// Starts a detached `cores revert-after` process that restores the state taken before the change
+/// after `duration`, so that the change gets reverted even if the terminal or SSH session is closed.
+/// `since` is the ID of the last journal entry before the change, see `revert_after()`.
+/// # Returns
+/// The PID of the detached process.
+pub fn spawn_revert_helper(duration: Duration, state_before: &CPUState, since: usize) -> Result<u32, Box<dyn Error>> {
+    let mut helper = Command::new(env::current_exe()?);
+    helper
+        .arg("revert-after")
+        .arg(duration::format(duration))
+        .arg(state_before.online_core_list())
+        .arg(since.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Safety: `setsid()` is async-signal-safe, hence may be called between `fork()` and `exec()`.
+    unsafe { helper.pre_exec(start_new_session) };
+
+    let child = helper.spawn()?;
+    Ok(child.id())
+}
+
+/// The body of the detached process started by `spawn_revert_helper()`: waits for `delay` and
+/// restores `cores_online`. A termination signal restores the state right away.
+pub fn run_revert_helper(delay: Duration, cores_online: &HashSet<usize>, since: usize) -> Result<usize, Box<dyn Error>> {
+    catch_interrupts();
+    wait_until(Instant::now() + delay);
+    revert(cores_online, &format!("--for {} --detach", duration::format(delay)), since)
+}
@@ -0,0 +1,257 @@
+use crate::change;
+use crate::core_list;
+use crate::cpu;
+use crate::cpu::Outcome;
+use crate::duration;
+use crate::exec::{command_from, exit_code, spawn_as_invoking_user, wait};
+use crate::journal;
+use crate::json;
+use crate::sys_linux::{catch_interrupts, interrupt_signal};
+use crate::timestamp;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::process::Child;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check for interrupts and for the command to exit between events.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A small deterministic pseudo-random number generator (SplitMix64), so that a chaos run can be
+/// reproduced from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns a duration in `min..=max`, with millisecond resolution.
+    fn duration_between(&mut self, min: Duration, max: Duration) -> Duration {
+        let span = (max - min).as_millis() as u64;
+        min + Duration::from_millis(self.next_u64() % (span + 1))
+    }
+}
+
+/// Parses the interval between chaos events: either a fixed duration (`10s`) or a range (`5s..30s`).
+pub fn parse_interval(input: &str) -> Result<(Duration, Duration), String> {
+    let (min, max) = match input.split_once("..") {
+        Some((min, max)) => (duration::parse(min)?, duration::parse(max)?),
+        None => {
+            let interval = duration::parse(input)?;
+            (interval, interval)
+        }
+    };
+    if min > max {
+        return Err(format!("Invalid interval '{}'. The minimum is greater than the maximum", input));
+    }
+    if max.is_zero() {
+        return Err(format!("Invalid interval '{}'. The interval must be greater than zero", input));
+    }
+    Ok((min, max))
+}
+
+/// Settings of a chaos run.
+pub struct Settings {
+    /// Stop injecting failures after this time. Without a command, `None` means until interrupted.
+    pub duration: Option<Duration>,
+    /// Never let the number of online cores drop below this.
+    pub min_online: usize,
+    /// The minimum and maximum time between two events.
+    pub interval: (Duration, Duration),
+    pub seed: u64,
+    pub as_json: bool,
+}
+
+/// Picks the next event: which core to set to which state. `None` if there is nothing to change.
+fn pick_event(rng: &mut Rng, hotpluggable: &[usize], online: &HashSet<usize>, min_online: usize) -> Option<(usize, bool)> {
+    let (online_candidates, offline_candidates): (Vec<usize>, Vec<usize>) =
+        hotpluggable.iter().partition(|core| online.contains(core));
+
+    let can_offline = !online_candidates.is_empty() && online.len() > min_online;
+    let can_online = !offline_candidates.is_empty();
+    let bring_online = match (can_offline, can_online) {
+        (false, false) => return None,
+        (true, false) => false,
+        (false, true) => true,
+        (true, true) => rng.below(2) == 0,
+    };
+
+    let candidates = if bring_online { &offline_candidates } else { &online_candidates };
+    Some((candidates[rng.below(candidates.len())], bring_online))
+}
+
+fn log_event(started: Instant, settings: &Settings, event: &str, core: Option<usize>, outcome: Result<(), String>) {
+    let online = cpu::get_state().map(|state| state.online_core_list()).unwrap_or_default();
+    let elapsed = started.elapsed().as_secs_f64();
+    let now = timestamp::format(timestamp::now());
+
+    if settings.as_json {
+        println!(
+            r#"{{"timestamp":"{}","elapsed_secs":{:.3},"event":"{}","core":{},"ok":{},"error":{},"online":"{}"}}"#,
+            now,
+            elapsed,
+            event,
+            core.map_or("null".to_string(), |core| core.to_string()),
+            outcome.is_ok(),
+            outcome.as_ref().err().map_or("null".to_string(), |e| json::string(e)),
+            online
+        );
+    } else {
+        let core = core.map_or(String::new(), |core| format!(" core {}", core));
+        match outcome {
+            Ok(()) => println!("{} +{:>9.3}s {}{} (online: {})", now, elapsed, event, core, online),
+            Err(e) => println!("{} +{:>9.3}s {}{} FAILED: {} (online: {})", now, elapsed, event, core, e, online),
+        }
+    }
+}
+
+/// Randomly takes hotpluggable cores offline and brings them back online until the duration
+/// elapses, the command exits or `cores` is interrupted, logging every event to stdout and
+/// recording it in the journal. The same
+/// seed and starting state produce the same sequence of events. The starting state is restored
+/// at the end. The output of the command goes to stderr to keep stdout for the event log.
+/// # Returns
+/// The exit code of the command if there is one, otherwise `128 + N` if interrupted by signal N or `0`.
+pub fn run(settings: &Settings, command: &[String]) -> Result<i32, Box<dyn Error>> {
+    let state_before = cpu::get_state()?;
+    let since = journal::last_id();
+    let hotpluggable = cpu::get_hotpluggable_cores()?;
+    if hotpluggable.is_empty() {
+        return Err(Box::from("There are no hotpluggable cores on this system"));
+    }
+
+    // From now on, an interrupt must not terminate `cores` before the previous state is restored
+    catch_interrupts();
+
+    let started = Instant::now();
+    log_event(started, settings, &format!("start seed={}", settings.seed), None, Ok(()));
+
+    let mut child: Option<Child> = None;
+    if !command.is_empty() {
+        let mut process = command_from(command);
+        process.stdout(io::stderr());
+        child = Some(spawn_as_invoking_user(process, None)?);
+    }
+
+    let mut rng = Rng(settings.seed);
+    let deadline = settings.duration.map(|duration| started + duration);
+    let mut next_event = started + rng.duration_between(settings.interval.0, settings.interval.1);
+    let mut exit_status = None;
+
+    loop {
+        if interrupt_signal().is_some() || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        if let Some(process) = child.as_mut() {
+            if let Some(status) = process.try_wait()? {
+                exit_status = Some(status);
+                break;
+            }
+        }
+
+        if Instant::now() >= next_event {
+            let online: HashSet<usize> = cpu::get_state()?.online_cores().into_iter().collect();
+            if let Some((core, bring_online)) = pick_event(&mut rng, &hotpluggable, &online, settings.min_online) {
+                let event = if bring_online { "online" } else { "offline" };
+                let core_states = HashMap::from([(core, bring_online)]);
+                let action = format!("chaos core {} {}", core, event);
+                let result = change::apply_transitions(&action, None, || cpu::set_cores_with_outcomes(&core_states));
+
+                let outcome = match result {
+                    Ok(result) => match result.transitions.first().map(|transition| &transition.outcome) {
+                        Some(Outcome::Changed) => Ok(()),
+                        Some(Outcome::Failed { error, .. }) => Err(error.clone()),
+                        _ => Err("the state was not changed".to_string()),
+                    },
+                    Err(e) => Err(e.to_string()),
+                };
+                log_event(started, settings, event, Some(core), outcome);
+            }
+            next_event = Instant::now() + rng.duration_between(settings.interval.0, settings.interval.1);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    let cores_online: HashSet<usize> = state_before.online_cores().into_iter().collect();
+    let reason = format!("revert to {} (chaos finished)", core_list::format(cores_online.iter().copied()));
+    let restored = change::restore(&cores_online, &reason, since);
+    log_event(
+        started,
+        settings,
+        "restore",
+        None,
+        restored.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    );
+
+    // Chaos is over, but the command may still be running
+    if let (Some(process), None) = (child, exit_status) {
+        exit_status = Some(wait(process)?.0);
+    }
+    restored?;
+
+    Ok(match (exit_status, interrupt_signal()) {
+        (Some(status), _) => exit_code(&status),
+        (None, Some(signum)) => 128 + signum,
+        (None, None) => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_reproducible() {
+        let (mut a, mut b) = (Rng(42), Rng(42));
+        let sequence_a: Vec<u64> = (0..5).map(|_| a.next_u64()).collect();
+        let sequence_b: Vec<u64> = (0..5).map(|_| b.next_u64()).collect();
+        assert_eq!(sequence_a, sequence_b);
+        assert_ne!(Rng(43).next_u64(), sequence_a[0]);
+    }
+
+    #[test]
+    fn test_duration_between() {
+        let mut rng = Rng(7);
+        for _ in 0..100 {
+            let d = rng.duration_between(Duration::from_secs(5), Duration::from_secs(30));
+            assert!(d >= Duration::from_secs(5) && d <= Duration::from_secs(30));
+        }
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("10s"), Ok((Duration::from_secs(10), Duration::from_secs(10))));
+        assert_eq!(parse_interval("5s..30s"), Ok((Duration::from_secs(5), Duration::from_secs(30))));
+        assert!(parse_interval("30s..5s").is_err());
+        assert!(parse_interval("0s").is_err());
+    }
+
+    #[test]
+    fn test_pick_event_respects_min_online() {
+        let mut rng = Rng(1);
+        let online: HashSet<usize> = [0, 1].into_iter().collect();
+        // Only core 1 could go offline, but 2 cores have to stay online: core 2 must come online
+        for _ in 0..20 {
+            assert_eq!(pick_event(&mut rng, &[1, 2], &online, 2), Some((2, true)));
+        }
+    }
+
+    #[test]
+    fn test_pick_event_nothing_to_do() {
+        let mut rng = Rng(1);
+        let online: HashSet<usize> = [0, 1].into_iter().collect();
+        assert_eq!(pick_event(&mut rng, &[1], &online, 2), None);
+    }
+}
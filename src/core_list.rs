@@ -1,3 +1,7 @@
+pub mod format;
 pub mod parse;
 
+pub use format::format;
 pub use parse::parse;
+pub use parse::parse_or_empty;
+pub use parse::try_parse;
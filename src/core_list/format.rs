@@ -0,0 +1,65 @@
+/// Formats a set of CPU core numbers as a string in the format of `/sys/devices/system/cpu/online`,
+/// i.e. the inverse of [`parse`](super::parse). Consecutive values are collapsed into ranges.
+///
+/// **Output examples:** `0`, `0-5`, `0-3,5`, `0-1,3,5`, `0-2,4-5`, or an empty string for no cores.
+pub fn format<I: IntoIterator<Item = usize>>(cores: I) -> String {
+    let mut cores: Vec<usize> = cores.into_iter().collect();
+    cores.sort_unstable();
+    cores.dedup();
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < cores.len() {
+        let start = cores[i];
+        let mut end = start;
+        // Extend the range while the values are consecutive.
+        while i + 1 < cores.len() && cores[i + 1] == end + 1 {
+            end += 1;
+            i += 1;
+        }
+        if start == end {
+            parts.push(start.to_string());
+        } else {
+            parts.push(format!("{}-{}", start, end));
+        }
+        i += 1;
+    }
+    parts.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core_list::parse;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(format(Vec::new()), "");
+    }
+
+    #[test]
+    fn test_single_value() {
+        assert_eq!(format(vec![3]), "3");
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(format(vec![0, 1, 2, 3]), "0-3");
+    }
+
+    #[test]
+    fn test_mixed_unordered_values() {
+        assert_eq!(format(vec![8, 0, 5, 1, 3, 6]), "0-1,3,5-6,8");
+    }
+
+    #[test]
+    fn test_duplicates() {
+        assert_eq!(format(vec![2, 2, 3, 3]), "2-3");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let input = "0-2,4,6-7";
+        assert_eq!(format(parse(input)), input);
+    }
+}
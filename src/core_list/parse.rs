@@ -32,6 +32,31 @@ pub fn parse(input: &str) -> HashSet<usize> {
     result
 }
 
+/// Parses a CPU list given by the user, like [`parse`], but returns an error for malformed input
+/// instead of panicking. Also usable as a `clap` value parser.
+pub fn try_parse(input: &str) -> Result<HashSet<usize>, String> {
+    let valid = !input.trim().is_empty()
+        && input.split(',').all(|part| {
+            part.matches('-').count() <= 1
+                && part
+                    .split('-')
+                    .all(|bound| !bound.trim().is_empty() && bound.trim().chars().all(|c| c.is_ascii_digit()))
+        });
+    if !valid {
+        return Err(format!("Invalid CPU list '{}'. Expected e.g. 0 | 0-5 | 1-3,5", input));
+    }
+    Ok(parse(input))
+}
+
+/// Parses a CPU list read from the kernel, like [`parse`], but accepts an empty list: the kernel
+/// reports no cores as an empty string, e.g. in `cpuset.cpus` of a new cgroup.
+pub fn parse_or_empty(input: &str) -> HashSet<usize> {
+    if input.trim().is_empty() {
+        return HashSet::new();
+    }
+    parse(input)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,6 +123,21 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_try_parse() {
+        assert_eq!(try_parse("0-2, 4"), Ok(to_set(&[0, 1, 2, 4])));
+        assert!(try_parse("a-b").is_err());
+        assert!(try_parse("1-2-3").is_err());
+        assert!(try_parse("1,,2").is_err());
+        assert!(try_parse("").is_err());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(parse_or_empty(" \n"), HashSet::new());
+        assert_eq!(parse_or_empty("1-2"), to_set(&[1, 2]));
+    }
+
     #[test]
     fn test_invalid_input() {
         let input = "a-b"; // Invalid input, will panic
@@ -1,11 +1,37 @@
+pub mod boot_params;
 pub mod cpu_state;
+pub mod cpuset;
+pub mod freq;
 mod get_state;
+pub mod hotplug;
+pub mod idle;
+mod irq;
+pub mod list;
+pub mod pinned;
+pub mod pstate;
 mod render;
+pub mod report;
+mod saved;
 mod set_state;
+pub mod topology;
+pub mod usage;
 
 
+pub use crate::cpu::get_state::get_hotpluggable_cores;
 pub use crate::cpu::get_state::get_state;
 pub use crate::cpu::render::render;
+pub use crate::cpu::render::render_change;
+pub use crate::cpu::render::render_marked;
+pub use crate::cpu::render::Format;
+pub use crate::cpu::render::Layout;
+pub use crate::cpu::render::Marks;
+pub use crate::cpu::render::Only;
+pub use crate::cpu::render::View;
+pub use crate::cpu::set_state::count_changed;
 pub use crate::cpu::set_state::reset_cores;
+pub use crate::cpu::set_state::reset_cores_with_outcomes;
 pub use crate::cpu::set_state::set_cores;
-pub use crate::cpu::set_state::set_core_state;
\ No newline at end of file
+pub use crate::cpu::set_state::set_cores_with_outcomes;
+pub use crate::cpu::set_state::set_core_state;
+pub use crate::cpu::set_state::Outcome;
+pub use crate::cpu::set_state::Outcomes;
@@ -0,0 +1,168 @@
+use crate::core_list;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+
+const PROC_CMDLINE: &str = "/proc/cmdline";
+/// The cores isolated by the kernel command line (`isolcpus=`).
+pub const LINUX_CPU_ISOLATED: &str = "/sys/devices/system/cpu/isolated";
+const LINUX_CPU_NOHZ_FULL: &str = "/sys/devices/system/cpu/nohz_full";
+pub const LINUX_SMT_CONTROL: &str = "/sys/devices/system/cpu/smt/control";
+
+/// The kernel command line parameters that affect CPU hotplug and isolation.
+const RELEVANT_PARAMS: [&str; 6] = ["maxcpus", "nr_cpus", "possible_cpus", "nosmt", "isolcpus", "nohz_full"];
+
+/// `EBUSY`, returned when the kernel refuses to take a core offline.
+const EBUSY: i32 = 16;
+
+/// Boot-time settings that affect CPU hotplug and isolation.
+#[derive(Debug, Default)]
+pub struct BootParams {
+    /// The relevant parameters of the kernel command line, e.g. `("maxcpus", "2")` or `("nosmt", "")`.
+    pub cmdline: Vec<(String, String)>,
+    /// The cores isolated with `isolcpus=`.
+    pub isolated: HashSet<usize>,
+    /// The cores running in adaptive-tick mode with `nohz_full=`.
+    pub nohz_full: HashSet<usize>,
+    /// The SMT control: `on`, `off`, `forceoff`, `notsupported` or `notimplemented`.
+    pub smt_control: Option<String>,
+}
+
+/// Extracts the relevant parameters from the kernel command line. The arguments after `--` are
+/// passed to init and ignored.
+fn parse_cmdline(content: &str) -> Vec<(String, String)> {
+    content
+        .split_whitespace()
+        .take_while(|param| *param != "--")
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (param.to_string(), String::new()),
+        })
+        .filter(|(name, _)| RELEVANT_PARAMS.contains(&name.as_str()))
+        .collect()
+}
+
+fn read_cpu_list(path: &str) -> HashSet<usize> {
+    fs::read_to_string(path).map_or_else(|_| HashSet::new(), |content| core_list::parse_or_empty(&content))
+}
+
+/// Reads the SMT control: `on`, `off`, `forceoff`, `notsupported` or `notimplemented`, `None` if
+/// the kernel has no SMT control (older than 4.19).
+pub fn smt_control() -> Option<String> {
+    fs::read_to_string(LINUX_SMT_CONTROL).ok().map(|content| content.trim().to_string())
+}
+
+/// Reads the boot-time settings from `/proc/cmdline` and sysfs. Missing files are treated as unset.
+pub fn get() -> BootParams {
+    BootParams {
+        cmdline: fs::read_to_string(PROC_CMDLINE).map_or_else(|_| Vec::new(), |content| parse_cmdline(&content)),
+        isolated: read_cpu_list(LINUX_CPU_ISOLATED),
+        nohz_full: read_cpu_list(LINUX_CPU_NOHZ_FULL),
+        smt_control: smt_control(),
+    }
+}
+
+impl BootParams {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.cmdline.iter().find(|(param, _)| param == name).map(|(_, value)| value.as_str())
+    }
+
+    fn is_smt_disabled(&self) -> bool {
+        matches!(self.smt_control.as_deref(), Some("off") | Some("forceoff"))
+    }
+
+    /// Whether there is anything to report, see `notes()`.
+    pub fn is_relevant(&self) -> bool {
+        !self.cmdline.is_empty() || !self.isolated.is_empty() || !self.nohz_full.is_empty() || self.is_smt_disabled()
+    }
+
+    /// Explains the effect of the settings on `cores`, one note per setting.
+    pub fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        for (name, value) in &self.cmdline {
+            let note = match name.as_str() {
+                "maxcpus" => format!("only {} core(s) were brought up at boot, the others can be brought online", value),
+                "nr_cpus" | "possible_cpus" => {
+                    format!("at most {} core(s) can be present, the others can't be brought online until reboot", value)
+                }
+                "nosmt" if value == "force" => "SMT siblings are offline and can't be brought online until reboot".to_string(),
+                "nosmt" => "SMT siblings are offline, run `echo on > /sys/devices/system/cpu/smt/control` to allow bringing them online".to_string(),
+                "isolcpus" => "the isolated cores are not used for general scheduling".to_string(),
+                "nohz_full" => "the adaptive-tick cores depend on housekeeping cores, which can't all be taken offline".to_string(),
+                _ => continue,
+            };
+            let param = if value.is_empty() { name.clone() } else { format!("{}={}", name, value) };
+            notes.push(format!("{}: {}", param, note));
+        }
+        if self.is_smt_disabled() && self.param("nosmt").is_none() {
+            notes.push(format!(
+                "SMT control is '{}': SMT siblings can't be brought online",
+                self.smt_control.as_deref().unwrap_or_default()
+            ));
+        }
+        notes
+    }
+
+    /// Explains why the kernel refused to change the state of a core, if it's due to a boot-time setting.
+    /// # Arguments
+    /// * `is_online` - The state the core was to be set to.
+    /// * `e` - The error returned when writing `cpuN/online`.
+    pub fn explain_failure(&self, is_online: bool, e: &io::Error) -> Option<String> {
+        if is_online && e.kind() == io::ErrorKind::PermissionDenied && self.is_smt_disabled() {
+            return Some(match self.smt_control.as_deref() {
+                Some("forceoff") => "SMT is disabled until reboot (nosmt=force), this core is likely an SMT sibling".to_string(),
+                _ => "SMT is disabled and this core is likely an SMT sibling, run `echo on > /sys/devices/system/cpu/smt/control` first".to_string(),
+            });
+        }
+        if !is_online && e.raw_os_error() == Some(EBUSY) && !self.nohz_full.is_empty() {
+            return Some(format!(
+                "With nohz_full={}, the kernel keeps at least one housekeeping core online",
+                core_list::format(self.nohz_full.iter().copied())
+            ));
+        }
+        None
+    }
+
+    /// Explains why cores requested by the user don't exist, if it's due to a boot-time setting.
+    pub fn explain_missing(&self) -> Option<String> {
+        let (name, value) = self.cmdline.iter().find(|(name, _)| name == "nr_cpus" || name == "possible_cpus")?;
+        Some(format!("The kernel was booted with {}={}, more cores can't be present until reboot", name, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cmdline() {
+        let cmdline = "BOOT_IMAGE=/vmlinuz root=/dev/sda1 maxcpus=2 nosmt isolcpus=2-3 quiet -- nr_cpus=1";
+        assert_eq!(
+            parse_cmdline(cmdline),
+            vec![
+                ("maxcpus".to_string(), "2".to_string()),
+                ("nosmt".to_string(), String::new()),
+                ("isolcpus".to_string(), "2-3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notes() {
+        let params = BootParams { cmdline: parse_cmdline("maxcpus=2 nosmt=force"), ..Default::default() };
+        let notes = params.notes();
+        assert_eq!(notes.len(), 2);
+        assert!(notes[0].starts_with("maxcpus=2: only 2 core(s)"));
+        assert!(notes[1].starts_with("nosmt=force: "));
+        assert!(!BootParams::default().is_relevant());
+    }
+
+    #[test]
+    fn test_explain_failure() {
+        let params = BootParams { smt_control: Some("off".to_string()), ..Default::default() };
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(params.explain_failure(true, &denied).is_some());
+        assert!(params.explain_failure(false, &denied).is_none());
+        assert!(BootParams::default().explain_failure(true, &denied).is_none());
+    }
+}
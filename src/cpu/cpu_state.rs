@@ -1,4 +1,9 @@
-#[derive(Debug)]
+use crate::core_list;
+use crate::cpu::boot_params::BootParams;
+use crate::cpu::freq::CoreFreq;
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Default)]
 /// Represents the state of the CPU(s) on the system.
 pub struct CPUState {
     /// The total number of cores on the system.
@@ -9,4 +14,46 @@ pub struct CPUState {
     pub cores_offline: usize,
     /// A vector of booleans representing the state of each core. `true` means the core is enabled, `false` means the core is disabled.
     pub ordered_core_states: Vec<bool>,
-}
\ No newline at end of file
+}
+
+/// What is shown along with the state of the cores. Read only when rendering the state, not by
+/// every `cpu::get_state()`, see `get_state::get_details()`.
+#[derive(Debug, Default)]
+pub struct StateDetails {
+    /// The cores removed from general scheduling, by an isolated cpuset partition or `isolcpus=`.
+    pub isolated_cores: HashSet<usize>,
+    /// Boot-time settings that affect CPU hotplug and isolation.
+    pub boot_params: BootParams,
+    /// The cpufreq settings of the cores with a cpufreq policy.
+    pub frequencies: BTreeMap<usize, CoreFreq>,
+    /// Whether boost (turbo) is enabled, `None` if the cpufreq driver has no boost switch.
+    pub boost: Option<bool>,
+}
+
+impl CPUState {
+    /// Returns the indices of the online cores in ascending order.
+    pub fn online_cores(&self) -> Vec<usize> {
+        self.ordered_core_states
+            .iter()
+            .enumerate()
+            .filter(|(_, is_online)| **is_online)
+            .map(|(core, _)| core)
+            .collect()
+    }
+
+    /// Returns the online cores in CPU list format, e.g. `0-3,5`.
+    pub fn online_core_list(&self) -> String {
+        core_list::format(self.online_cores())
+    }
+
+    /// Returns the offline cores in CPU list format, e.g. `4,6-7`.
+    pub fn offline_core_list(&self) -> String {
+        core_list::format(
+            self.ordered_core_states
+                .iter()
+                .enumerate()
+                .filter(|(_, is_online)| !**is_online)
+                .map(|(core, _)| core),
+        )
+    }
+}
@@ -0,0 +1,241 @@
+pub mod partition;
+
+use crate::core_list;
+use crate::cpu;
+use crate::cpu::saved::{self, CoreSets, SavedCoreSets};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const PROC_MOUNTS: &str = "/proc/mounts";
+const V1_EFFECTIVE_FILE: &str = "cpuset.effective_cpus";
+const V2_EFFECTIVE_FILE: &str = "cpuset.cpus.effective";
+
+/// The original cpus of the cpusets that lost cores taken offline.
+const SAVED_CPUSETS: SavedCoreSets = SavedCoreSets { file: "/var/lib/cores/cpusets" };
+
+/// A mounted cgroup hierarchy that may contain cpusets.
+#[derive(Debug, PartialEq)]
+struct Hierarchy {
+    root: PathBuf,
+    /// The file with the configured cpus.
+    cpus_file: &'static str,
+    /// The file with the cpus actually available to the cgroup.
+    effective_file: &'static str,
+}
+
+/// Finds the cgroup v1 hierarchies with the cpuset controller and the cgroup v2 hierarchy in `/proc/mounts`.
+fn parse_mounts(content: &str) -> Vec<Hierarchy> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (mount_point, fs_type, options) = (fields.get(1)?, fields.get(2)?, fields.get(3)?);
+            let (cpus_file, effective_file) = match *fs_type {
+                "cgroup" if options.split(',').any(|option| option == "cpuset") => ("cpuset.cpus", V1_EFFECTIVE_FILE),
+                "cgroup2" => ("cpuset.cpus", V2_EFFECTIVE_FILE),
+                _ => return None,
+            };
+            Some(Hierarchy { root: PathBuf::from(mount_point), cpus_file, effective_file })
+        })
+        .collect()
+}
+
+fn hierarchies() -> Vec<Hierarchy> {
+    fs::read_to_string(PROC_MOUNTS).map_or_else(|_| Vec::new(), |content| parse_mounts(&content))
+}
+
+/// Returns the mount point of the cgroup v2 hierarchy, if mounted.
+fn cgroup2_root() -> Option<PathBuf> {
+    hierarchies()
+        .into_iter()
+        .find(|hierarchy| hierarchy.effective_file == V2_EFFECTIVE_FILE)
+        .map(|hierarchy| hierarchy.root)
+}
+
+/// Collects `dir` and all the cgroups below it.
+fn walk(dir: &Path, cgroups: &mut Vec<PathBuf>) {
+    cgroups.push(dir.to_path_buf());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            walk(&entry.path(), cgroups);
+        }
+    }
+}
+
+fn read_cpu_list(path: &Path) -> Option<HashSet<usize>> {
+    fs::read_to_string(path).ok().map(|content| core_list::parse_or_empty(&content))
+}
+
+/// Reads the configured cpus of all the cpusets, except the root ones which the kernel keeps in
+/// sync with the online cores.
+fn read_cpus() -> CoreSets<String> {
+    let mut cpus = CoreSets::new();
+    for hierarchy in hierarchies() {
+        let mut cgroups = Vec::new();
+        walk(&hierarchy.root, &mut cgroups);
+        for cgroup in cgroups.into_iter().skip(1) {
+            if let Some(cores) = read_cpu_list(&cgroup.join(hierarchy.cpus_file)) {
+                cpus.insert(cgroup.join(hierarchy.cpus_file).to_string_lossy().to_string(), cores);
+            }
+        }
+    }
+    cpus
+}
+
+/// A snapshot of the configured cpus of the cpusets, taken before a change.
+pub struct Snapshot(CoreSets<String>);
+
+impl Snapshot {
+    /// Reads the configured cpus of the cpusets in the snapshot again, without walking the cgroup
+    /// hierarchies. Cpusets removed meanwhile are left out.
+    fn reread(&self) -> CoreSets<String> {
+        self.0
+            .keys()
+            .filter_map(|cpus_file| Some((cpus_file.clone(), read_cpu_list(Path::new(cpus_file))?)))
+            .collect()
+    }
+}
+
+/// Saves the configured cpus of the cpusets that include cores going offline, so that they can be
+/// restored once the cores are back online: on cgroup v1 the kernel removes offline cores from
+/// `cpuset.cpus` and never adds them back. Requires root privileges.
+/// # Returns
+/// The cpus of all the cpusets before the change, see `restore()` and `report_changes()`.
+pub fn save(going_offline: &HashSet<usize>) -> Snapshot {
+    let current = read_cpus();
+    SAVED_CPUSETS.save(&current, going_offline);
+    Snapshot(current)
+}
+
+/// Restores the saved cpus of the cpusets that included cores that are back online, limited to the
+/// cores that are online. Parents are restored before their children. Requires root privileges.
+/// # Arguments
+/// * `before` - The snapshot taken by `save()` before the change, its cpusets are the ones restored.
+/// * `back_online` - The cores brought back online.
+/// * `online` - All the cores online now.
+pub fn restore(before: &Snapshot, back_online: &HashSet<usize>, online: &HashSet<usize>) {
+    if back_online.is_empty() {
+        return;
+    }
+    for (cpus_file, cores) in SAVED_CPUSETS.restore(&before.reread(), back_online, online) {
+        let cpu_list = core_list::format(cores.iter().copied());
+        if let Err(e) = fs::write(&cpus_file, &cpu_list) {
+            eprintln!("Could not restore {} to {}. {}", cpus_file, cpu_list, e);
+        }
+    }
+}
+
+/// Reports the cpusets whose configured cpus changed since `before` to stderr.
+pub fn report_changes(before: &Snapshot) {
+    saved::report_changes(&before.0, &before.reread(), |cpus_file| format!("cpuset {}", cpus_file));
+}
+
+/// A cpuset whose effective cpus differ from the online cores.
+#[derive(Serialize, Debug)]
+pub struct Confined {
+    pub cgroup: String,
+    /// The configured cpus, in CPU list format.
+    pub cpus: String,
+    /// The cpus actually available to the cgroup.
+    pub effective: String,
+    /// The online cores that are not available to the cgroup.
+    pub missing: String,
+    /// The original cpus saved by `cores` when it took cores offline, if not restored yet.
+    pub saved: Option<String>,
+}
+
+/// Lists the cgroups whose effective cpus differ from the online cores, e.g. the ones that lost
+/// cores when they went offline. Cgroups without the cpuset controller are skipped.
+pub fn audit() -> Result<Vec<Confined>, Box<dyn Error>> {
+    let online: HashSet<usize> = cpu::get_state()?.online_cores().into_iter().collect();
+    let saved: CoreSets<String> = SAVED_CPUSETS.read();
+
+    let mut confined = Vec::new();
+    for hierarchy in hierarchies() {
+        let mut cgroups = Vec::new();
+        walk(&hierarchy.root, &mut cgroups);
+        for cgroup in cgroups {
+            let Some(effective) = read_cpu_list(&cgroup.join(hierarchy.effective_file)) else {
+                continue;
+            };
+            if effective == online {
+                continue;
+            }
+            let cpus_file = cgroup.join(hierarchy.cpus_file);
+            confined.push(Confined {
+                cgroup: cgroup.to_string_lossy().to_string(),
+                cpus: core_list::format(read_cpu_list(&cpus_file).unwrap_or_default()),
+                effective: core_list::format(effective.iter().copied()),
+                missing: core_list::format(online.difference(&effective).copied()),
+                saved: saved
+                    .get(cpus_file.to_string_lossy().as_ref())
+                    .map(|cores| core_list::format(cores.iter().copied())),
+            });
+        }
+    }
+    Ok(confined)
+}
+
+fn render_as_text(confined: &[Confined]) {
+    if confined.is_empty() {
+        println!("All the cpusets have all the online cores available");
+        return;
+    }
+    println!("{:<12}  {:<12}  {:<12}  {:<12}  CGROUP", "CPUS", "EFFECTIVE", "MISSING", "SAVED");
+    let or_dash = |cpu_list: &str| if cpu_list.is_empty() { "-".to_string() } else { cpu_list.to_string() };
+    for cpuset in confined {
+        println!(
+            "{:<12}  {:<12}  {:<12}  {:<12}  {}",
+            or_dash(&cpuset.cpus),
+            or_dash(&cpuset.effective),
+            or_dash(&cpuset.missing),
+            or_dash(cpuset.saved.as_deref().unwrap_or_default()),
+            cpuset.cgroup
+        );
+    }
+}
+
+/// Renders the result of `audit()` in the desired format.
+pub fn render(confined: &[Confined], as_json: bool) {
+    if as_json {
+        println!("{}", serde_json::to_string(confined).unwrap_or_default());
+    } else {
+        render_as_text(confined);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = "\
+tmpfs /sys/fs/cgroup tmpfs rw,relatime,mode=755 0 0
+cgroup /sys/fs/cgroup/cpu cgroup rw,relatime,cpu 0 0
+cgroup /sys/fs/cgroup/cpuset cgroup rw,relatime,cpuset 0 0
+cgroup2 /sys/fs/cgroup/unified cgroup2 rw,relatime 0 0
+";
+        assert_eq!(
+            parse_mounts(mounts),
+            vec![
+                Hierarchy {
+                    root: PathBuf::from("/sys/fs/cgroup/cpuset"),
+                    cpus_file: "cpuset.cpus",
+                    effective_file: "cpuset.effective_cpus"
+                },
+                Hierarchy {
+                    root: PathBuf::from("/sys/fs/cgroup/unified"),
+                    cpus_file: "cpuset.cpus",
+                    effective_file: "cpuset.cpus.effective"
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::core_list;
+use crate::cpu::boot_params::LINUX_CPU_ISOLATED;
+use crate::cpu::cpuset::cgroup2_root;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The cgroup holding the isolated partition, directly below the cgroup v2 root.
+const PARTITION_CGROUP: &str = "cores.isolated";
+
+fn read_cpu_list(path: &Path) -> HashSet<usize> {
+    fs::read_to_string(path).map_or_else(|_| HashSet::new(), |content| core_list::parse_or_empty(&content))
+}
+
+fn write(path: &Path, value: &str) -> Result<(), Box<dyn Error>> {
+    fs::write(path, value).map_err(|e| Box::from(format!("Could not write '{}' to {}. {}", value, path.display(), e)))
+}
+
+/// Returns the cgroup of the partition, which may not exist yet. Fails if the cpuset controller is
+/// not available in the cgroup v2 hierarchy.
+pub fn partition_cgroup() -> Result<PathBuf, Box<dyn Error>> {
+    let root = cgroup2_root().ok_or("Isolated partitions require cgroup v2, but it is not mounted")?;
+    let controllers = fs::read_to_string(root.join("cgroup.controllers")).unwrap_or_default();
+    if !controllers.split_whitespace().any(|controller| controller == "cpuset") {
+        return Err(Box::from(format!(
+            "The cpuset controller is not available in the cgroup v2 hierarchy at {}. Is it bound to cgroup v1?",
+            root.display()
+        )));
+    }
+    Ok(root.join(PARTITION_CGROUP))
+}
+
+/// Returns the state of the partition as reported by the kernel, e.g. `isolated` or
+/// `isolated invalid (Cpu list in cpuset.cpus not exclusive)`.
+fn partition_state(cgroup: &Path) -> String {
+    fs::read_to_string(cgroup.join("cpuset.cpus.partition")).unwrap_or_default().trim().to_string()
+}
+
+/// Returns the cores isolated from general scheduling, by an isolated cpuset partition
+/// (e.g. created with `isolate()`) or on the kernel command line with `isolcpus=`.
+pub fn isolated_cores() -> HashSet<usize> {
+    let mut isolated = read_cpu_list(Path::new(LINUX_CPU_ISOLATED));
+    if let Some(root) = cgroup2_root() {
+        // Reported by kernels 6.8 and later for all the isolated partitions
+        isolated.extend(read_cpu_list(&root.join("cpuset.cpus.isolated")));
+
+        let cgroup = root.join(PARTITION_CGROUP);
+        if partition_state(&cgroup) == "isolated" {
+            isolated.extend(read_cpu_list(&cgroup.join("cpuset.cpus.effective")));
+        }
+    }
+    isolated
+}
+
+/// Returns the cores in the isolated partition created by `isolate()`, empty if there is none.
+pub fn partition_cores() -> HashSet<usize> {
+    cgroup2_root().map_or_else(HashSet::new, |root| read_cpu_list(&root.join(PARTITION_CGROUP).join("cpuset.cpus")))
+}
+
+/// Removes `cores` from general scheduling without taking them offline, by adding them to an
+/// isolated cgroup v2 cpuset partition (`cpuset.cpus.partition=isolated`). The cores are then
+/// available only to processes moved to the partition's cgroup, and not load balanced, like with
+/// `isolcpus=` on the kernel command line. Requires root privileges.
+/// # Returns
+/// The number of cores isolated.
+pub fn isolate(cores: &HashSet<usize>) -> Result<usize, Box<dyn Error>> {
+    // Don't ever isolate the core 0, like it's never taken offline.
+    if cores.contains(&0) {
+        return Err(Box::from("Core 0 can't be isolated"));
+    }
+    let cgroup = partition_cgroup()?;
+    let isolated_before = isolated_cores();
+
+    let created = match fs::create_dir(&cgroup) {
+        Ok(()) => true,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => false,
+        Err(e) => return Err(Box::from(format!("Could not create cgroup {}. {}", cgroup.display(), e))),
+    };
+    let root = cgroup.parent().unwrap_or(Path::new("/"));
+    let subtree_control = fs::read_to_string(root.join("cgroup.subtree_control")).unwrap_or_default();
+    let result = (|| {
+        if !subtree_control.split_whitespace().any(|controller| controller == "cpuset") {
+            write(&root.join("cgroup.subtree_control"), "+cpuset")?;
+        }
+        let mut cpus = read_cpu_list(&cgroup.join("cpuset.cpus"));
+        cpus.extend(cores);
+        write(&cgroup.join("cpuset.cpus"), &core_list::format(cpus))?;
+        if partition_state(&cgroup) != "isolated" {
+            write(&cgroup.join("cpuset.cpus.partition"), "isolated")?;
+        }
+
+        let state = partition_state(&cgroup);
+        match state.as_str() {
+            "isolated" => Ok(()),
+            // E.g. `isolated invalid (Cpu list in cpuset.cpus not exclusive)`
+            _ => Err(Box::from(format!("The kernel did not isolate the cores: {}", state))),
+        }
+    })();
+
+    if let Err(e) = result {
+        if created {
+            let _ = remove(&cgroup);
+        }
+        return Err(e);
+    }
+    Ok(isolated_cores().difference(&isolated_before).count())
+}
+
+/// Turns the partition back into a regular cgroup and removes it.
+fn remove(cgroup: &Path) -> Result<(), Box<dyn Error>> {
+    write(&cgroup.join("cpuset.cpus.partition"), "member")?;
+    fs::remove_dir(cgroup).map_err(|e| {
+        Box::from(format!(
+            "Could not remove cgroup {}. {}. Move its processes to another cgroup first",
+            cgroup.display(),
+            e
+        ))
+    })
+}
+
+/// Returns cores isolated by `isolate()` to general scheduling. Requires root privileges.
+/// # Arguments
+/// * `cores` - The cores to return, `None` for all of them. When no isolated cores remain, the partition is removed.
+/// # Returns
+/// The number of cores returned to general scheduling.
+pub fn unisolate(cores: Option<&HashSet<usize>>) -> Result<usize, Box<dyn Error>> {
+    let cgroup = partition_cgroup()?;
+    if !cgroup.exists() {
+        return Ok(0);
+    }
+    let isolated_before = isolated_cores();
+
+    let mut cpus = read_cpu_list(&cgroup.join("cpuset.cpus"));
+    match cores {
+        Some(cores) => cpus.retain(|core| !cores.contains(core)),
+        None => cpus.clear(),
+    }
+    if cpus.is_empty() {
+        remove(&cgroup)?;
+    } else {
+        write(&cgroup.join("cpuset.cpus"), &core_list::format(cpus))?;
+    }
+
+    Ok(isolated_before.difference(&isolated_cores()).count())
+}
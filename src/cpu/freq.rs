@@ -0,0 +1,337 @@
+use crate::core_list;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Boost switch of the `acpi-cpufreq` and `amd-pstate` drivers, `1` when boost is enabled.
+const LINUX_CPUFREQ_BOOST: &str = "/sys/devices/system/cpu/cpufreq/boost";
+/// Boost switch of the `intel_pstate` driver, `1` when turbo is *disabled*.
+const LINUX_INTEL_NO_TURBO: &str = "/sys/devices/system/cpu/intel_pstate/no_turbo";
+
+pub const NO_CPUFREQ_MSG: &str = "Is a cpufreq driver loaded? VMs and containers often don't expose cpufreq";
+
+/// The cpufreq settings of a core, as in `/sys/devices/system/cpu/cpuN/cpufreq/`. All the
+/// frequencies are in kHz, like in sysfs.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CoreFreq {
+    /// The scaling driver, e.g. `intel_pstate` or `acpi-cpufreq`.
+    pub driver: Option<String>,
+    pub governor: Option<String>,
+    pub available_governors: Vec<String>,
+    /// The current frequency, as far as the kernel knows.
+    pub cur_khz: Option<u64>,
+    /// The lower limit set for the governor.
+    pub min_khz: Option<u64>,
+    /// The upper limit set for the governor.
+    pub max_khz: Option<u64>,
+    /// The lowest frequency supported by the hardware.
+    pub hw_min_khz: Option<u64>,
+    /// The highest frequency supported by the hardware.
+    pub hw_max_khz: Option<u64>,
+    /// The energy performance preference, e.g. `balance_performance`, with `intel_pstate` and `amd-pstate`.
+    pub epp: Option<String>,
+    pub available_epps: Vec<String>,
+}
+
+/// The changes to make to the cpufreq settings of cores, `None` to keep a setting.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FreqSettings {
+    pub governor: Option<String>,
+    pub min_khz: Option<u64>,
+    pub max_khz: Option<u64>,
+}
+
+impl FreqSettings {
+    pub fn is_empty(&self) -> bool {
+        self.governor.is_none() && self.min_khz.is_none() && self.max_khz.is_none()
+    }
+}
+
+pub fn cpufreq_path(core: usize, file: &str) -> String {
+    format!("/sys/devices/system/cpu/cpu{}/cpufreq/{}", core, file)
+}
+
+fn read_list(path: &str) -> Vec<String> {
+    read_string(path).map(|list| list.split_whitespace().map(str::to_string).collect()).unwrap_or_default()
+}
+
+fn read_string(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok().map(|content| content.trim().to_string())
+}
+
+fn read_khz(core: usize, file: &str) -> Option<u64> {
+    read_string(&cpufreq_path(core, file))?.parse().ok()
+}
+
+/// Reads the cpufreq settings of `core`, `None` if it has no cpufreq policy.
+pub fn read(core: usize) -> Option<CoreFreq> {
+    if !Path::new(&cpufreq_path(core, "")).exists() {
+        return None;
+    }
+    Some(CoreFreq {
+        driver: read_string(&cpufreq_path(core, "scaling_driver")),
+        governor: read_string(&cpufreq_path(core, "scaling_governor")),
+        available_governors: read_list(&cpufreq_path(core, "scaling_available_governors")),
+        cur_khz: read_khz(core, "scaling_cur_freq"),
+        min_khz: read_khz(core, "scaling_min_freq"),
+        max_khz: read_khz(core, "scaling_max_freq"),
+        hw_min_khz: read_khz(core, "cpuinfo_min_freq"),
+        hw_max_khz: read_khz(core, "cpuinfo_max_freq"),
+        epp: read_string(&cpufreq_path(core, "energy_performance_preference")),
+        available_epps: read_list(&cpufreq_path(core, "energy_performance_available_preferences")),
+    })
+}
+
+/// Reads the cpufreq settings of all the `total_cores` cores that have a cpufreq policy.
+pub fn read_all(total_cores: usize) -> BTreeMap<usize, CoreFreq> {
+    (0..total_cores).filter_map(|core| Some((core, read(core)?))).collect()
+}
+
+/// Parses a frequency with an optional unit, e.g. `1.8GHz`, `1800MHz` or `1800000kHz`.
+/// Without a unit, the number is in kHz like in sysfs. Usable as a `clap` value parser.
+/// # Returns
+/// The frequency in kHz.
+pub fn parse_frequency(frequency: &str) -> Result<u64, String> {
+    let frequency = frequency.trim();
+    let split = frequency.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(frequency.len());
+    let (number, unit) = frequency.split_at(split);
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "khz" => 1.0,
+        "mhz" => 1_000.0,
+        "ghz" => 1_000_000.0,
+        _ => return Err(format!("Unknown unit '{}', use kHz, MHz or GHz, e.g. 1.8GHz", unit.trim())),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("Invalid frequency '{}', e.g. 1.8GHz", frequency))?;
+    let khz = (number * multiplier).round();
+    if khz < 1.0 {
+        return Err(format!("Invalid frequency '{}', it must be positive", frequency));
+    }
+    Ok(khz as u64)
+}
+
+/// Formats a frequency in kHz for humans, e.g. `1.80 GHz` or `800 MHz`.
+pub fn format_frequency(khz: u64) -> String {
+    if khz >= 1_000_000 {
+        format!("{:.2} GHz", khz as f64 / 1_000_000.0)
+    } else {
+        format!("{} MHz", khz / 1_000)
+    }
+}
+
+/// Returns whether boost (turbo) is enabled, `None` if the driver has no boost switch.
+pub fn get_boost() -> Option<bool> {
+    if let Some(boost) = read_string(LINUX_CPUFREQ_BOOST) {
+        return Some(boost == "1");
+    }
+    read_string(LINUX_INTEL_NO_TURBO).map(|no_turbo| no_turbo == "0")
+}
+
+/// Enables or disables boost (turbo) for all the cores. Requires root privileges.
+pub fn set_boost(is_enabled: bool) -> Result<(), Box<dyn Error>> {
+    let (path, value) = if Path::new(LINUX_CPUFREQ_BOOST).exists() {
+        (LINUX_CPUFREQ_BOOST, if is_enabled { "1" } else { "0" })
+    } else if Path::new(LINUX_INTEL_NO_TURBO).exists() {
+        (LINUX_INTEL_NO_TURBO, if is_enabled { "0" } else { "1" })
+    } else {
+        return Err(Box::from(format!("The cpufreq driver has no boost switch. {}", NO_CPUFREQ_MSG)));
+    };
+    fs::write(path, value).map_err(|e| Box::from(format!("Could not write '{}' to {}. {}", value, path, e)))
+}
+
+/// Checks `settings` against what the hardware and the driver of a core support.
+fn validate(core: usize, freq: &CoreFreq, settings: &FreqSettings) -> Result<(), String> {
+    if let Some(governor) = &settings.governor {
+        if !freq.available_governors.is_empty() && !freq.available_governors.contains(governor) {
+            return Err(format!(
+                "Core {} doesn't support the governor '{}', available: {}",
+                core,
+                governor,
+                freq.available_governors.join(", ")
+            ));
+        }
+    }
+    for khz in settings.min_khz.iter().chain(settings.max_khz.iter()) {
+        let below = freq.hw_min_khz.is_some_and(|hw_min| *khz < hw_min);
+        let above = freq.hw_max_khz.is_some_and(|hw_max| *khz > hw_max);
+        if below || above {
+            return Err(format!(
+                "Core {} supports frequencies from {} to {}, not {}",
+                core,
+                freq.hw_min_khz.map_or("?".to_string(), format_frequency),
+                freq.hw_max_khz.map_or("?".to_string(), format_frequency),
+                format_frequency(*khz)
+            ));
+        }
+    }
+    let min = settings.min_khz.or(freq.min_khz);
+    let max = settings.max_khz.or(freq.max_khz);
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(format!(
+                "The minimum frequency of core {} ({}) would exceed the maximum ({})",
+                core,
+                format_frequency(min),
+                format_frequency(max)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn write(core: usize, file: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let path = cpufreq_path(core, file);
+    fs::write(&path, value).map_err(|e| Box::from(format!("Could not write '{}' to {}. {}", value, path, e)))
+}
+
+/// Changes the governor and the frequency limits of `cores`. All the cores are validated before
+/// anything is written, offline cores and cores without a cpufreq policy are skipped with a warning.
+/// Requires root privileges.
+/// # Returns
+/// The number of cores whose settings changed.
+pub fn set(cores: &HashSet<usize>, settings: &FreqSettings) -> Result<usize, Box<dyn Error>> {
+    let mut cores: Vec<usize> = cores.iter().copied().collect();
+    cores.sort_unstable();
+
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for core in cores {
+        match read(core) {
+            Some(freq) => {
+                validate(core, &freq, settings)?;
+                targets.push((core, freq));
+            }
+            None => skipped.push(core),
+        }
+    }
+    if targets.is_empty() {
+        return Err(Box::from(format!(
+            "Core(s) {} have no cpufreq policy. {}",
+            core_list::format(skipped),
+            NO_CPUFREQ_MSG
+        )));
+    }
+    if !skipped.is_empty() {
+        eprintln!("Core(s) {} have no cpufreq policy (offline?) and are skipped", core_list::format(skipped));
+    }
+
+    let mut cores_updated = 0;
+    for (core, before) in targets {
+        if let Some(governor) = &settings.governor {
+            write(core, "scaling_governor", governor)?;
+        }
+        // The kernel rejects a minimum above the current maximum, and vice versa, so the order matters.
+        let raises_min_above_max = matches!((settings.min_khz, before.max_khz), (Some(min), Some(max)) if min > max);
+        let limits = if raises_min_above_max {
+            [("scaling_max_freq", settings.max_khz), ("scaling_min_freq", settings.min_khz)]
+        } else {
+            [("scaling_min_freq", settings.min_khz), ("scaling_max_freq", settings.max_khz)]
+        };
+        for (file, khz) in limits {
+            if let Some(khz) = khz {
+                write(core, file, &khz.to_string())?;
+            }
+        }
+        let after = read(core).unwrap_or_default();
+        let changed = (after.governor, after.min_khz, after.max_khz) != (before.governor, before.min_khz, before.max_khz);
+        cores_updated += usize::from(changed);
+    }
+    Ok(cores_updated)
+}
+
+/// Describes the cpufreq settings of a core in a few words, e.g. `2.40 GHz powersave balance_power`.
+pub fn summary(freq: &CoreFreq) -> String {
+    let mut parts = Vec::new();
+    if let Some(cur_khz) = freq.cur_khz {
+        parts.push(format_frequency(cur_khz));
+    }
+    if let Some(governor) = &freq.governor {
+        parts.push(governor.clone());
+    }
+    if let Some(epp) = &freq.epp {
+        parts.push(epp.clone());
+    }
+    parts.join(" ")
+}
+
+fn render_as_text(frequencies: &BTreeMap<usize, CoreFreq>, boost: Option<bool>) {
+    let or_dash = |khz: Option<u64>| khz.map_or("-".to_string(), format_frequency);
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!("{:>4}  {:<12}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}", "CORE", "GOVERNOR", "CURRENT", "MIN", "MAX", "HW MIN", "HW MAX");
+    for (core, freq) in frequencies {
+        println!(
+            "{:>4}  {:<12}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+            core,
+            freq.governor.as_deref().unwrap_or("-"),
+            or_dash(freq.cur_khz),
+            or_dash(freq.min_khz),
+            or_dash(freq.max_khz),
+            or_dash(freq.hw_min_khz),
+            or_dash(freq.hw_max_khz)
+        );
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    let driver = frequencies.values().find_map(|freq| freq.driver.as_deref()).unwrap_or("-");
+    let boost = boost.map_or("not available", |is_enabled| if is_enabled { "on" } else { "off" });
+    println!("driver: {}, boost: {}", driver, boost);
+}
+
+#[derive(Serialize)]
+struct Rendered<'a> {
+    cores: &'a BTreeMap<usize, CoreFreq>,
+    boost: Option<bool>,
+}
+
+/// Renders the cpufreq settings of the cores.
+/// # Arguments
+/// * `frequencies` - The settings of the cores with a cpufreq policy, see `read_all()`.
+/// * `boost` - Whether boost is enabled, see `get_boost()`.
+/// * `as_json` - Whether to render in JSON format or in human-readable text format.
+pub fn render(frequencies: &BTreeMap<usize, CoreFreq>, boost: Option<bool>, as_json: bool) {
+    if as_json {
+        println!("{}", serde_json::to_string(&Rendered { cores: frequencies, boost }).unwrap_or_default());
+    } else if frequencies.is_empty() {
+        println!("No core has a cpufreq policy. {}", NO_CPUFREQ_MSG);
+    } else {
+        render_as_text(frequencies, boost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_frequency() {
+        assert_eq!(parse_frequency("1.8GHz"), Ok(1_800_000));
+        assert_eq!(parse_frequency("1800 MHz"), Ok(1_800_000));
+        assert_eq!(parse_frequency("800000"), Ok(800_000));
+        assert_eq!(parse_frequency("800000kHz"), Ok(800_000));
+        assert!(parse_frequency("1.8THz").is_err());
+        assert!(parse_frequency("fast").is_err());
+        assert!(parse_frequency("0").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let freq = CoreFreq {
+            available_governors: vec!["performance".to_string(), "powersave".to_string()],
+            min_khz: Some(800_000),
+            max_khz: Some(2_000_000),
+            hw_min_khz: Some(800_000),
+            hw_max_khz: Some(3_400_000),
+            ..Default::default()
+        };
+        let settings = |governor: Option<&str>, min_khz, max_khz| FreqSettings {
+            governor: governor.map(str::to_string),
+            min_khz,
+            max_khz,
+        };
+        assert!(validate(2, &freq, &settings(Some("powersave"), None, Some(1_800_000))).is_ok());
+        assert!(validate(2, &freq, &settings(Some("ondemand"), None, None)).is_err());
+        assert!(validate(2, &freq, &settings(None, None, Some(4_000_000))).is_err());
+        assert!(validate(2, &freq, &settings(None, Some(2_500_000), None)).is_err());
+        assert!(validate(2, &freq, &settings(None, Some(2_500_000), Some(3_000_000))).is_ok());
+    }
+}
@@ -1,7 +1,11 @@
 use crate::core_list;
-use crate::cpu::cpu_state::CPUState;
+use crate::cpu::boot_params;
+use crate::cpu::cpu_state::{CPUState, StateDetails};
+use crate::cpu::cpuset;
+use crate::cpu::freq;
 use std::error::Error;
 use std::fs;
+use std::path::Path;
 
 const LINUX_CPU_ONLINE: &str = "/sys/devices/system/cpu/online";
 const LINUX_CPU_PRESENT: &str = "/sys/devices/system/cpu/present";
@@ -33,6 +37,18 @@ fn get_online_cores() -> Result<Vec<usize>, Box<dyn Error>> {
     }
 }
 
+/// Returns the cores whose state can be changed, i.e. the ones with `cpuN/online` in sysfs.
+/// Core 0 is never included, as `cores` doesn't change its state.
+pub fn get_hotpluggable_cores() -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut cores: Vec<usize> = get_present_cores()?
+        .into_iter()
+        .filter(|core| *core != 0)
+        .filter(|core| Path::new(&format!("/sys/devices/system/cpu/cpu{}/online", core)).exists())
+        .collect();
+    cores.sort_unstable();
+    Ok(cores)
+}
+
 pub fn get_state() -> Result<CPUState, Box<dyn Error>> {
     let cores_present = get_present_cores()?;
     let online_cores = get_online_cores()?;
@@ -55,3 +71,15 @@ pub fn get_state() -> Result<CPUState, Box<dyn Error>> {
     })
 
 }
+
+/// Reads what is shown along with the state of the cores, see `StateDetails`.
+/// # Arguments
+/// * `total_cores` - The number of cores, see `CPUState`.
+pub fn get_details(total_cores: usize) -> StateDetails {
+    StateDetails {
+        isolated_cores: cpuset::partition::isolated_cores(),
+        boot_params: boot_params::get(),
+        frequencies: freq::read_all(total_cores),
+        boost: freq::get_boost(),
+    }
+}
@@ -0,0 +1,223 @@
+use crate::core_list;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const LINUX_HOTPLUG_STATES: &str = "/sys/devices/system/cpu/hotplug/states";
+
+/// The value of `cpuN/hotplug/fail` when no failure is injected.
+const NO_FAILURE: i32 = -1;
+
+const STATE_CONTROL_MSG: &str =
+    "Intermediate states require a kernel built with CONFIG_CPU_HOTPLUG_STATE_CONTROL, \
+     and only states with callbacks outside of the STARTING section can fail";
+
+/// A state of the kernel's CPU hotplug state machine, as listed in `/sys/devices/system/cpu/hotplug/states`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HotplugState {
+    pub number: i32,
+    pub name: String,
+}
+
+/// The hotplug state of a core, as in `/sys/devices/system/cpu/cpuN/hotplug/`.
+#[derive(Serialize, Debug)]
+pub struct CoreHotplug {
+    pub core: usize,
+    /// The state the core is currently at.
+    pub state: i32,
+    /// The state the core is being brought to.
+    pub target: i32,
+    /// The state at which the next hotplug operation of the core fails, if any.
+    pub fail: Option<i32>,
+}
+
+fn hotplug_path(core: usize, file: &str) -> String {
+    format!("/sys/devices/system/cpu/cpu{}/hotplug/{}", core, file)
+}
+
+fn read_number(path: &str) -> Result<i32, Box<dyn Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Could not read {}. {}", path, e))?;
+    content
+        .trim()
+        .parse()
+        .map_err(|e| Box::from(format!("Unexpected content of {}: '{}'. {}", path, content.trim(), e)))
+}
+
+/// Parses the content of `/sys/devices/system/cpu/hotplug/states`, one `<number>: <name>` per line.
+fn parse_states(content: &str) -> Vec<HotplugState> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (number, name) = line.split_once(':')?;
+            Some(HotplugState {
+                number: number.trim().parse().ok()?,
+                name: name.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the states of the CPU hotplug state machine, ordered from `offline` to `online`.
+pub fn get_states() -> Result<Vec<HotplugState>, Box<dyn Error>> {
+    match fs::read_to_string(LINUX_HOTPLUG_STATES) {
+        Ok(content) => Ok(parse_states(&content)),
+        Err(e) => Err(Box::from(format!(
+            "Could not read {LINUX_HOTPLUG_STATES}. {e}. Is the kernel built with CONFIG_HOTPLUG_CPU?"
+        ))),
+    }
+}
+
+/// Returns the hotplug state of every core that exposes it, ordered by core.
+pub fn get_cores() -> Result<Vec<CoreHotplug>, Box<dyn Error>> {
+    let present = fs::read_to_string("/sys/devices/system/cpu/present")?;
+    let mut cores: Vec<usize> = core_list::parse(present.trim()).into_iter().collect();
+    cores.sort_unstable();
+
+    cores
+        .into_iter()
+        .filter(|core| Path::new(&hotplug_path(*core, "state")).exists())
+        .map(|core| {
+            let fail = read_number(&hotplug_path(core, "fail"))?;
+            Ok(CoreHotplug {
+                core,
+                state: read_number(&hotplug_path(core, "state"))?,
+                target: read_number(&hotplug_path(core, "target"))?,
+                fail: (fail != NO_FAILURE).then_some(fail),
+            })
+        })
+        .collect()
+}
+
+/// Resolves a state given by number (`235`) or by name (`sched:active`) to its number.
+pub fn resolve_state(states: &[HotplugState], input: &str) -> Result<i32, String> {
+    let input = input.trim();
+    let found = match input.parse::<i32>() {
+        Ok(number) => states.iter().find(|state| state.number == number),
+        Err(_) => states.iter().find(|state| state.name == input),
+    };
+    found.map(|state| state.number).ok_or(format!(
+        "Unknown hotplug state '{}'. Run `cores hotplug states` to list the states",
+        input
+    ))
+}
+
+/// Returns the name of the state with the given number, for messages.
+pub fn state_name(states: &[HotplugState], number: i32) -> &str {
+    states
+        .iter()
+        .find(|state| state.number == number)
+        .map_or("?", |state| state.name.as_str())
+}
+
+fn check_core(core: usize) -> Result<(), Box<dyn Error>> {
+    // Don't ever change state of the core 0.
+    if core == 0 {
+        return Err(Box::from("Core 0 is not hotplugged by `cores`"));
+    }
+    if !Path::new(&hotplug_path(core, "state")).exists() {
+        return Err(Box::from(format!("Core {} does not exist or is not hotpluggable", core)));
+    }
+    Ok(())
+}
+
+fn explain(e: io::Error) -> String {
+    if e.kind() == io::ErrorKind::InvalidInput {
+        format!("{}. {}", e, STATE_CONTROL_MSG)
+    } else {
+        e.to_string()
+    }
+}
+
+/// Injects a failure: the next hotplug operation of `core` fails at state `state`, in either
+/// direction, and the core is rolled back to where the operation started.
+/// Requires root privileges.
+/// # Arguments
+/// * `state` - The state to fail at, `None` to remove a previously injected failure.
+pub fn set_fail(core: usize, state: Option<i32>) -> Result<(), Box<dyn Error>> {
+    check_core(core)?;
+    let value = state.unwrap_or(NO_FAILURE);
+    fs::write(hotplug_path(core, "fail"), value.to_string()).map_err(|e| {
+        Box::from(format!("Could not set the hotplug failure of core {} to {}. {}", core, value, explain(e)))
+    })
+}
+
+/// Brings `core` to the hotplug state `state`, which may be an intermediate state to park the core
+/// between `offline` and `online`. Requires root privileges.
+/// # Returns
+/// `1` if the core changed its state, `0` otherwise, like the other functions changing the cores.
+pub fn set_target(core: usize, state: i32) -> Result<usize, Box<dyn Error>> {
+    check_core(core)?;
+    let state_before = read_number(&hotplug_path(core, "state"))?;
+    fs::write(hotplug_path(core, "target"), state.to_string()).map_err(|e| {
+        format!("Could not set the hotplug target of core {} to {}. {}", core, state, explain(e))
+    })?;
+    Ok(usize::from(read_number(&hotplug_path(core, "state"))? != state_before))
+}
+
+fn render_as_text(states: &[HotplugState], cores: &[CoreHotplug]) {
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!("{:>5}  {:<40}  CORES AT STATE", "STATE", "NAME");
+    for state in states {
+        let at_state = core_list::format(cores.iter().filter(|c| c.state == state.number).map(|c| c.core));
+        println!("{:>5}  {:<40}  {}", state.number, state.name, at_state);
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!("{:>4}  {:<24}  {:<24}  FAIL AT", "CORE", "STATE", "TARGET");
+    let describe = |number: i32| format!("{} ({})", number, state_name(states, number));
+    for core in cores {
+        println!(
+            "{:>4}  {:<24}  {:<24}  {}",
+            core.core,
+            describe(core.state),
+            describe(core.target),
+            core.fail.map_or("-".to_string(), describe)
+        );
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+}
+
+#[derive(Serialize)]
+struct Rendered<'a> {
+    states: &'a [HotplugState],
+    cores: &'a [CoreHotplug],
+}
+
+/// Renders the hotplug state machine and the hotplug state of the cores.
+/// # Arguments
+/// * `states` - The states, see `get_states()`.
+/// * `cores` - The hotplug state of the cores, see `get_cores()`.
+/// * `as_json` - Whether to render in JSON format or in human-readable text format.
+pub fn render(states: &[HotplugState], cores: &[CoreHotplug], as_json: bool) {
+    if as_json {
+        println!("{}", serde_json::to_string(&Rendered { states, cores }).unwrap_or_default());
+    } else {
+        render_as_text(states, cores);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states() -> Vec<HotplugState> {
+        parse_states("  0: offline\n  1: threads:prepare\n235: sched:active\n236: online\n")
+    }
+
+    #[test]
+    fn test_parse_states() {
+        let states = states();
+        assert_eq!(states.len(), 4);
+        assert_eq!(states[2], HotplugState { number: 235, name: "sched:active".to_string() });
+    }
+
+    #[test]
+    fn test_resolve_state() {
+        let states = states();
+        assert_eq!(resolve_state(&states, "sched:active"), Ok(235));
+        assert_eq!(resolve_state(&states, "1"), Ok(1));
+        assert!(resolve_state(&states, "2").is_err());
+        assert!(resolve_state(&states, "sched").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::core_list;
+use crate::journal::JOURNAL_DIR;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const LINUX_CPUIDLE_DRIVER: &str = "/sys/devices/system/cpu/cpuidle/current_driver";
+
+/// The `disable` settings of the idle states changed by `cores`, as they were before the first
+/// change, so that they can be restored with `restore()`. On disk every idle state is a line
+/// `<core>\t<state index>\t<disable>`.
+const SAVED_IDLE_STATES: &str = "/var/lib/cores/cpuidle";
+
+const NO_CPUIDLE_MSG: &str = "Is a cpuidle driver loaded? VMs often don't expose idle states";
+
+/// An idle state (C-state) of a core, as in `/sys/devices/system/cpu/cpuN/cpuidle/stateK/`.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct IdleState {
+    /// The `K` of `stateK`, deeper states have higher numbers.
+    pub index: usize,
+    /// E.g. `POLL`, `C1`, `C6`.
+    pub name: String,
+    pub desc: String,
+    /// The exit latency in microseconds.
+    pub latency_us: u64,
+    /// The minimum time in microseconds the core should stay in the state to save power.
+    pub residency_us: u64,
+    /// The number of times the state was entered.
+    pub usage: u64,
+    /// The total time in microseconds spent in the state.
+    pub time_us: u64,
+    pub disabled: bool,
+}
+
+/// The idle states of a core.
+#[derive(Serialize, Debug)]
+pub struct CoreIdle {
+    pub core: usize,
+    pub states: Vec<IdleState>,
+}
+
+fn state_path(core: usize, index: usize, file: &str) -> String {
+    format!("/sys/devices/system/cpu/cpu{}/cpuidle/state{}/{}", core, index, file)
+}
+
+fn read_string(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_default().trim().to_string()
+}
+
+fn read_number(path: &str) -> u64 {
+    read_string(path).parse().unwrap_or_default()
+}
+
+/// Reads the idle states of `core`, an empty list if it has none (e.g. it's offline).
+pub fn read(core: usize) -> Vec<IdleState> {
+    (0..)
+        .take_while(|index| Path::new(&state_path(core, *index, "")).exists())
+        .map(|index| IdleState {
+            index,
+            name: read_string(&state_path(core, index, "name")),
+            desc: read_string(&state_path(core, index, "desc")),
+            latency_us: read_number(&state_path(core, index, "latency")),
+            residency_us: read_number(&state_path(core, index, "residency")),
+            usage: read_number(&state_path(core, index, "usage")),
+            time_us: read_number(&state_path(core, index, "time")),
+            disabled: read_string(&state_path(core, index, "disable")) == "1",
+        })
+        .collect()
+}
+
+/// Reads the idle states of all the `total_cores` cores that have any.
+pub fn read_all(total_cores: usize) -> Vec<CoreIdle> {
+    (0..total_cores)
+        .map(|core| CoreIdle { core, states: read(core) })
+        .filter(|core_idle| !core_idle.states.is_empty())
+        .collect()
+}
+
+/// Finds an idle state by name (case-insensitive, e.g. `C6`) or as `stateK`.
+fn resolve(states: &[IdleState], name: &str) -> Option<usize> {
+    states
+        .iter()
+        .find(|state| state.name.eq_ignore_ascii_case(name) || format!("state{}", state.index) == name)
+        .map(|state| state.index)
+}
+
+type Saved = BTreeMap<(usize, usize), bool>;
+
+fn read_saved() -> Saved {
+    let content = fs::read_to_string(SAVED_IDLE_STATES).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [core, index, disable] = fields[..] else {
+                return None;
+            };
+            Some(((core.parse().ok()?, index.parse().ok()?), disable == "1"))
+        })
+        .collect()
+}
+
+fn write_saved(saved: &Saved) -> Result<(), Box<dyn Error>> {
+    let result = if saved.is_empty() {
+        fs::remove_file(SAVED_IDLE_STATES).or_else(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(e),
+        })
+    } else {
+        let content: String = saved
+            .iter()
+            .map(|((core, index), disabled)| format!("{}\t{}\t{}\n", core, index, u8::from(*disabled)))
+            .collect();
+        fs::create_dir_all(JOURNAL_DIR).and_then(|_| fs::write(SAVED_IDLE_STATES, content))
+    };
+    result.map_err(|e| Box::from(format!("Could not save the idle states in {}. {}", SAVED_IDLE_STATES, e)))
+}
+
+/// Disables or enables the idle state `index` of `core`. Requires root privileges.
+pub fn write_disable(core: usize, index: usize, disabled: bool) -> Result<(), Box<dyn Error>> {
+    let path = state_path(core, index, "disable");
+    let value = if disabled { "1" } else { "0" };
+    fs::write(&path, value).map_err(|e| Box::from(format!("Could not write '{}' to {}. {}", value, path, e)))
+}
+
+/// Disables or enables idle states of `cores`, e.g. the deep C-states for latency tests. The
+/// previous settings are saved in `/var/lib/cores/cpuidle` before the first change, so that they
+/// can be restored with `restore()`. Cores without idle states (e.g. offline ones) are skipped with
+/// a warning. Requires root privileges.
+/// # Arguments
+/// * `names` - The idle states to change, by name (e.g. `C6`) or as `stateK`.
+/// * `disabled` - Whether to disable or enable the idle states.
+/// # Returns
+/// The number of idle states changed, over all the cores.
+pub fn set_disabled(cores: &HashSet<usize>, names: &[String], disabled: bool) -> Result<usize, Box<dyn Error>> {
+    let mut cores: Vec<usize> = cores.iter().copied().collect();
+    cores.sort_unstable();
+
+    // Resolve all the states on all the cores before changing anything.
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for core in cores {
+        let states = read(core);
+        if states.is_empty() {
+            skipped.push(core);
+            continue;
+        }
+        for name in names {
+            let index = resolve(&states, name).ok_or_else(|| {
+                let available: Vec<&str> = states.iter().map(|state| state.name.as_str()).collect();
+                format!("Core {} has no idle state '{}', available: {}", core, name, available.join(", "))
+            })?;
+            targets.push((core, index, states[index].disabled));
+        }
+    }
+    if targets.is_empty() {
+        return Err(Box::from(format!("Core(s) {} have no idle states. {}", core_list::format(skipped), NO_CPUIDLE_MSG)));
+    }
+    if !skipped.is_empty() {
+        eprintln!("Core(s) {} have no idle states (offline?) and are skipped", core_list::format(skipped));
+    }
+
+    let mut saved = read_saved();
+    let saved_before = saved.len();
+    for (core, index, was_disabled) in &targets {
+        saved.entry((*core, *index)).or_insert(*was_disabled);
+    }
+    if saved.len() != saved_before {
+        write_saved(&saved)?;
+    }
+
+    let mut states_updated = 0;
+    for (core, index, was_disabled) in targets {
+        if was_disabled != disabled {
+            write_disable(core, index, disabled)?;
+            states_updated += 1;
+        }
+    }
+    Ok(states_updated)
+}
+
+/// Restores the idle state settings saved by `set_disabled()` and forgets them. Idle states of
+/// cores that are offline now are kept for a later restore. Requires root privileges.
+/// # Returns
+/// The number of idle states changed, over all the cores.
+pub fn restore() -> Result<usize, Box<dyn Error>> {
+    let mut saved = read_saved();
+    if saved.is_empty() {
+        return Err(Box::from(format!("There are no saved idle state settings in {}", SAVED_IDLE_STATES)));
+    }
+    let mut states_updated = 0;
+    let mut restored = Vec::new();
+    for ((core, index), disabled) in &saved {
+        let Some(state) = read(*core).into_iter().nth(*index) else {
+            continue; // the core is offline
+        };
+        if state.disabled != *disabled {
+            write_disable(*core, *index, *disabled)?;
+            states_updated += 1;
+        }
+        restored.push((*core, *index));
+    }
+    for key in restored {
+        saved.remove(&key);
+    }
+    write_saved(&saved)?;
+    if !saved.is_empty() {
+        eprintln!("The idle states of offline cores are kept in {} to be restored later", SAVED_IDLE_STATES);
+    }
+    Ok(states_updated)
+}
+
+fn render_as_text(cores: &[CoreIdle]) {
+    let driver = read_string(LINUX_CPUIDLE_DRIVER);
+    println!("――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!(
+        "{:<8}  {:<10}  {:>10}  {:>10}  {:>12}  {:>12}  DISABLED ON",
+        "STATE", "NAME", "LATENCY", "RESIDENCY", "USAGE", "TIME"
+    );
+    // The states are the same on all the cores as far as names and latencies go, sum up the rest.
+    let states = cores.iter().map(|core_idle| core_idle.states.len()).max().unwrap_or_default();
+    for index in 0..states {
+        let per_core: Vec<(usize, &IdleState)> =
+            cores.iter().filter_map(|core_idle| Some((core_idle.core, core_idle.states.get(index)?))).collect();
+        let Some((_, first)) = per_core.first() else {
+            continue;
+        };
+        let disabled_on = core_list::format(per_core.iter().filter(|(_, state)| state.disabled).map(|(core, _)| *core));
+        println!(
+            "{:<8}  {:<10}  {:>7} us  {:>7} us  {:>12}  {:>11.1}s  {}",
+            format!("state{}", index),
+            first.name,
+            first.latency_us,
+            first.residency_us,
+            per_core.iter().map(|(_, state)| state.usage).sum::<u64>(),
+            per_core.iter().map(|(_, state)| state.time_us).sum::<u64>() as f64 / 1_000_000.0,
+            if disabled_on.is_empty() { "-".to_string() } else { disabled_on }
+        );
+    }
+    println!("――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!(
+        "driver: {}, cores: {}{}",
+        if driver.is_empty() { "-" } else { &driver },
+        core_list::format(cores.iter().map(|core_idle| core_idle.core)),
+        if Path::new(SAVED_IDLE_STATES).exists() { ", saved settings: run `cores idle --restore` to restore them" } else { "" }
+    );
+}
+
+/// Renders the idle states of the cores, usage and time are summed up over the cores in text format.
+/// # Arguments
+/// * `cores` - The idle states of the cores, see `read_all()`.
+/// * `as_json` - Whether to render in JSON format or in human-readable text format.
+pub fn render(cores: &[CoreIdle], as_json: bool) {
+    if as_json {
+        println!("{}", serde_json::to_string(cores).unwrap_or_default());
+    } else if cores.is_empty() {
+        println!("No core has idle states. {}", NO_CPUIDLE_MSG);
+    } else {
+        render_as_text(cores);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let states: Vec<IdleState> = ["POLL", "C1", "C1E", "C6"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| IdleState { index, name: name.to_string(), ..Default::default() })
+            .collect();
+        assert_eq!(resolve(&states, "C6"), Some(3));
+        assert_eq!(resolve(&states, "c1e"), Some(2));
+        assert_eq!(resolve(&states, "state1"), Some(1));
+        assert_eq!(resolve(&states, "C7"), None);
+    }
+}
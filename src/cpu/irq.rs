@@ -0,0 +1,83 @@
+use crate::core_list;
+use crate::cpu::saved::{self, CoreSets, SavedCoreSets};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+
+const PROC_IRQ: &str = "/proc/irq";
+
+/// The original affinity of the IRQs moved away from cores taken offline.
+const SAVED_AFFINITIES: SavedCoreSets = SavedCoreSets { file: "/var/lib/cores/irq_affinity" };
+
+/// The CPU affinity of IRQs: the cores each IRQ may be handled on.
+pub type Affinities = CoreSets<u32>;
+
+/// An IRQ whose affinity changed in a change of the state of the cores, e.g. moved away by the
+/// kernel from a core going offline.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MovedIrq {
+    pub irq: u32,
+    /// The cores before the change, in CPU list format.
+    pub from: String,
+    /// The cores after the change.
+    pub to: String,
+}
+
+fn affinity_path(irq: u32) -> String {
+    format!("{}/{}/smp_affinity_list", PROC_IRQ, irq)
+}
+
+/// Reads the CPU affinity of all the IRQs from `/proc/irq/<N>/smp_affinity_list`.
+/// IRQs whose affinity can't be read are skipped.
+pub fn read_affinities() -> Affinities {
+    let Ok(entries) = fs::read_dir(PROC_IRQ) else {
+        return Affinities::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|irq| {
+            let affinity = fs::read_to_string(affinity_path(irq)).ok()?;
+            Some((irq, core_list::parse(affinity.trim())))
+        })
+        .collect()
+}
+
+/// Saves the affinity of the IRQs that may be handled on the cores going offline, so that it can be
+/// restored once they are back online: the kernel moves such IRQs away and never moves them back.
+/// Requires root privileges.
+/// # Arguments
+/// * `current` - The current affinities, see `read_affinities()`.
+/// * `going_offline` - The cores about to be taken offline.
+pub fn save(current: &Affinities, going_offline: &HashSet<usize>) {
+    SAVED_AFFINITIES.save(current, going_offline);
+}
+
+/// Restores the saved affinity of the IRQs that may be handled on cores that are back online,
+/// limited to the cores that are online. Requires root privileges.
+/// # Arguments
+/// * `back_online` - The cores brought back online.
+/// * `online` - All the cores online now.
+pub fn restore(back_online: &HashSet<usize>, online: &HashSet<usize>) {
+    for (irq, cores) in SAVED_AFFINITIES.restore(&read_affinities(), back_online, online) {
+        let cpu_list = core_list::format(cores.iter().copied());
+        if let Err(e) = fs::write(affinity_path(irq), &cpu_list) {
+            eprintln!("Could not restore the affinity of IRQ {} to {}. {}", irq, cpu_list, e);
+        }
+    }
+}
+
+/// Reports the IRQs whose affinity changed since `before` to stderr.
+/// # Returns
+/// The IRQs whose affinity changed, in ascending order.
+pub fn report_moved(before: &Affinities) -> Vec<MovedIrq> {
+    let after = read_affinities();
+    saved::report_changes(before, &after, |irq| format!("IRQ {}", irq));
+    saved::changes(before, &after)
+        .into_iter()
+        .map(|(irq, from, to)| MovedIrq {
+            irq: *irq,
+            from: core_list::format(from.iter().copied()),
+            to: core_list::format(to.iter().copied()),
+        })
+        .collect()
+}
@@ -0,0 +1,209 @@
+use crate::cpu::cpu_state::{CPUState, StateDetails};
+use crate::cpu::get_state::get_details;
+use crate::cpu::topology::{self, CoreTopology};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// A column of `cores list`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Column {
+    Cpu,
+    Online,
+    Node,
+    Socket,
+    Core,
+    Type,
+    Mhz,
+    MinMhz,
+    MaxMhz,
+    Governor,
+    Isolated,
+}
+
+/// The columns with their names, as used with `-o` and in the headings.
+const COLUMNS: [(Column, &str); 11] = [
+    (Column::Cpu, "CPU"),
+    (Column::Online, "ONLINE"),
+    (Column::Node, "NODE"),
+    (Column::Socket, "SOCKET"),
+    (Column::Core, "CORE"),
+    (Column::Type, "TYPE"),
+    (Column::Mhz, "MHZ"),
+    (Column::MinMhz, "MINMHZ"),
+    (Column::MaxMhz, "MAXMHZ"),
+    (Column::Governor, "GOVERNOR"),
+    (Column::Isolated, "ISOLATED"),
+];
+
+pub const DEFAULT_COLUMNS: &str = "CPU,ONLINE,NODE,SOCKET,CORE,TYPE,MHZ,GOVERNOR";
+
+/// The description of the columns for `cores list --help`.
+pub const COLUMNS_HELP: &str = "\
+Available columns:
+   CPU        The logical CPU number
+   ONLINE     Whether the CPU is online (yes/no)
+   NODE       The NUMA node
+   SOCKET     The physical package
+   CORE       The physical core, shared by the SMT siblings
+   TYPE       The core type of hybrid CPUs (performance/efficiency)
+   MHZ        The current frequency in MHz
+   MINMHZ     The lowest frequency supported by the hardware in MHz
+   MAXMHZ     The highest frequency supported by the hardware in MHz
+   GOVERNOR   The cpufreq governor
+   ISOLATED   Whether the CPU is isolated from general scheduling (yes/no)
+
+Unknown values are shown as `-` (`null` in JSON), e.g. the socket and core of offline CPUs.";
+
+impl Column {
+    fn name(self) -> &'static str {
+        COLUMNS.iter().find(|(column, _)| *column == self).map_or("?", |(_, name)| name)
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Column::Cpu | Column::Node | Column::Socket | Column::Core | Column::Mhz | Column::MinMhz | Column::MaxMhz)
+    }
+}
+
+/// Parses a column name of `-o`, case-insensitive, e.g. `MHZ` or `governor`.
+pub fn parse_column(name: &str) -> Result<Column, String> {
+    COLUMNS
+        .iter()
+        .find(|(_, column_name)| column_name.eq_ignore_ascii_case(name.trim()))
+        .map(|(column, _)| *column)
+        .ok_or_else(|| {
+            let names: Vec<&str> = COLUMNS.iter().map(|(_, name)| *name).collect();
+            format!("unknown column `{}`, the columns are {}", name, names.join(","))
+        })
+}
+
+/// Everything known about the cores, read once for all the rows.
+struct Cores<'a> {
+    state: &'a CPUState,
+    details: StateDetails,
+    topologies: BTreeMap<usize, CoreTopology>,
+    core_types: HashMap<usize, &'static str>,
+}
+
+impl Cores<'_> {
+    /// The value of `column` for `core`, `null` if unknown.
+    fn value(&self, core: usize, column: Column) -> Value {
+        let topology = self.topologies.get(&core);
+        let core_freq = self.details.frequencies.get(&core);
+        let mhz = |khz: Option<u64>| json!(khz.map(|khz| khz / 1000));
+        match column {
+            Column::Cpu => json!(core),
+            Column::Online => json!(self.state.ordered_core_states.get(core)),
+            Column::Node => json!(topology.and_then(|topology| topology.node)),
+            Column::Socket => json!(topology.and_then(|topology| topology.package)),
+            Column::Core => json!(topology.and_then(|topology| topology.core_id)),
+            Column::Type => json!(self.core_types.get(&core)),
+            Column::Mhz => mhz(core_freq.and_then(|core_freq| core_freq.cur_khz)),
+            Column::MinMhz => mhz(core_freq.and_then(|core_freq| core_freq.hw_min_khz)),
+            Column::MaxMhz => mhz(core_freq.and_then(|core_freq| core_freq.hw_max_khz)),
+            Column::Governor => json!(core_freq.and_then(|core_freq| core_freq.governor.as_deref())),
+            Column::Isolated => json!(self.details.isolated_cores.contains(&core)),
+        }
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::Bool(true) => "yes".to_string(),
+        Value::Bool(false) => "no".to_string(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Lays out the rows as a table, the columns separated by a space. Numeric columns are aligned to
+/// the right, the others to the left, unless `raw`.
+fn format_table(columns: &[Column], rows: &[Vec<String>], raw: bool) -> Vec<String> {
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or_default())
+        .collect();
+    rows.iter()
+        .map(|row| {
+            if raw {
+                return row.join(" ");
+            }
+            let cells: Vec<String> = row
+                .iter()
+                .zip(columns.iter().zip(&widths))
+                .map(|(cell, (column, width))| match column.is_numeric() {
+                    true => format!("{:>width$}", cell, width = width),
+                    false => format!("{:<width$}", cell, width = width),
+                })
+                .collect();
+            cells.join(" ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Prints a table of the cores with the given columns, like `lscpu -e`.
+/// # Arguments
+/// * `state` - The state of the CPU(s) on the system.
+/// * `columns` - The columns to print, in order.
+/// * `no_headings` - Whether to leave out the line with the column names.
+/// * `raw` - Whether to separate the columns by a single space instead of aligning them.
+/// * `as_json` - Whether to print `{"cpus":[...]}` with the (lower case) columns of each core instead.
+pub fn render(state: &CPUState, columns: &[Column], no_headings: bool, raw: bool, as_json: bool) {
+    let cores = Cores {
+        state,
+        details: get_details(state.total_cores),
+        topologies: topology::get_all(state.total_cores),
+        core_types: topology::core_types(),
+    };
+
+    if as_json {
+        let cpus: Vec<Value> = (0..state.total_cores)
+            .map(|core| {
+                let map: Map<String, Value> =
+                    columns.iter().map(|column| (column.name().to_lowercase(), cores.value(core, *column))).collect();
+                Value::Object(map)
+            })
+            .collect();
+        println!("{}", json!({ "cpus": cpus }));
+        return;
+    }
+
+    let mut rows = Vec::new();
+    if !no_headings {
+        rows.push(columns.iter().map(|column| column.name().to_string()).collect());
+    }
+    for core in 0..state.total_cores {
+        rows.push(columns.iter().map(|column| to_text(&cores.value(core, *column))).collect());
+    }
+    for line in format_table(columns, &rows, raw) {
+        println!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_column() {
+        assert_eq!(parse_column("MHZ"), Ok(Column::Mhz));
+        assert_eq!(parse_column("governor"), Ok(Column::Governor));
+        assert!(parse_column("L1D").is_err());
+        for (_, name) in COLUMNS {
+            assert!(COLUMNS_HELP.contains(&format!("   {} ", name)), "{} is not described", name);
+        }
+    }
+
+    #[test]
+    fn test_format_table() {
+        let columns = [Column::Cpu, Column::Online, Column::Mhz, Column::Governor];
+        let rows: Vec<Vec<String>> = [["CPU", "ONLINE", "MHZ", "GOVERNOR"], ["9", "yes", "800", "powersave"], ["10", "no", "-", "-"]]
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        assert_eq!(
+            format_table(&columns, &rows, false),
+            vec!["CPU ONLINE MHZ GOVERNOR", "  9 yes    800 powersave", " 10 no       - -"]
+        );
+        assert_eq!(format_table(&columns, &rows, true)[2], "10 no - -");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::core_list;
+use crate::cpu;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// A process whose CPU affinity is restricted to some of the cores and would be affected by taking
+/// some of them offline. Threads have their own affinity, the threads of a process pinned to the
+/// same cores are reported together.
+#[derive(Debug, PartialEq)]
+pub struct PinnedTask {
+    pub pid: u32,
+    pub name: String,
+    /// The affected threads, in ascending order. The main thread has the same ID as the process.
+    pub tids: Vec<u32>,
+    /// The cores the threads are allowed to run on (`Cpus_allowed_list`).
+    pub allowed: HashSet<usize>,
+    /// The allowed cores that would remain online.
+    pub remaining: HashSet<usize>,
+}
+
+impl PinnedTask {
+    /// Whether none of the allowed cores would remain online. The kernel then breaks the affinity
+    /// of the process and moves it to any other core.
+    pub fn is_stranded(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    /// Whether only some of the threads are affected, not the process as a whole.
+    fn is_threads(&self) -> bool {
+        self.tids != [self.pid]
+    }
+}
+
+/// The fields of `/proc/<pid>/task/<tid>/status` relevant for CPU affinity.
+struct TaskStatus {
+    /// The thread ID.
+    pid: u32,
+    /// The process ID.
+    tgid: u32,
+    name: String,
+    allowed: HashSet<usize>,
+    is_kernel_thread: bool,
+}
+
+fn parse_status(content: &str) -> Option<TaskStatus> {
+    let field = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    };
+    let pid = field("Pid")?.parse().ok()?;
+    // `Kthread` is only reported by recent kernels, older ones are recognized by their parent `kthreadd` (PID 2)
+    let is_kernel_thread = match field("Kthread") {
+        Some(kthread) => kthread == "1",
+        None => pid == 2 || field("PPid") == Some("2"),
+    };
+    Some(TaskStatus {
+        pid,
+        tgid: field("Tgid").and_then(|tgid| tgid.parse().ok()).unwrap_or(pid),
+        name: field("Name").unwrap_or_default().to_string(),
+        allowed: core_list::parse(field("Cpus_allowed_list")?),
+        is_kernel_thread,
+    })
+}
+
+fn read_status(path: &Path) -> Option<TaskStatus> {
+    fs::read_to_string(path.join("status")).ok().as_deref().and_then(parse_status)
+}
+
+/// Reads the status of all the threads of all the processes, as each thread has its own CPU
+/// affinity. Processes and threads that exit meanwhile are skipped.
+fn read_tasks() -> Result<Vec<TaskStatus>, Box<dyn Error>> {
+    let mut tasks = Vec::new();
+    for entry in fs::read_dir("/proc").map_err(|e| format!("Could not read /proc. {}", e))? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        match fs::read_dir(entry.path().join("task")) {
+            Ok(threads) => tasks.extend(threads.flatten().filter_map(|thread| read_status(&thread.path()))),
+            Err(_) => tasks.extend(read_status(&entry.path())),
+        }
+    }
+    Ok(tasks)
+}
+
+/// Selects the threads pinned to some of the cores going offline and groups them by process and
+/// allowed cores. Threads allowed on all the cores online before the change are not pinned, and
+/// per-CPU kernel threads are handled by the kernel.
+fn affected(tasks: Vec<TaskStatus>, online_before: &HashSet<usize>, online_after: &HashSet<usize>) -> Vec<PinnedTask> {
+    let going_offline: HashSet<usize> = online_before.difference(online_after).copied().collect();
+    let mut affected: BTreeMap<(u32, Vec<usize>), PinnedTask> = BTreeMap::new();
+    for task in tasks
+        .into_iter()
+        .filter(|task| !task.is_kernel_thread)
+        .filter(|task| !online_before.is_subset(&task.allowed))
+        .filter(|task| !task.allowed.is_disjoint(&going_offline))
+    {
+        let mut allowed: Vec<usize> = task.allowed.iter().copied().collect();
+        allowed.sort_unstable();
+        let pinned = affected.entry((task.tgid, allowed)).or_insert_with(|| PinnedTask {
+            pid: task.tgid,
+            name: task.name.clone(),
+            tids: Vec::new(),
+            remaining: task.allowed.intersection(online_after).copied().collect(),
+            allowed: task.allowed,
+        });
+        if task.pid == task.tgid {
+            pinned.name = task.name; // the name of the process rather than of one of its threads
+        }
+        pinned.tids.push(task.pid);
+    }
+    affected
+        .into_values()
+        .map(|mut pinned| {
+            pinned.tids.sort_unstable();
+            pinned
+        })
+        .collect()
+}
+
+/// Finds the processes that would lose some or all of their allowed cores by applying `core_states`.
+/// # Arguments
+/// * `core_states` - The desired state of the cores, as for `cpu::set_cores()`. Cores not in the map remain unchanged.
+pub fn find_affected(core_states: &HashMap<usize, bool>) -> Result<Vec<PinnedTask>, Box<dyn Error>> {
+    let online_before: HashSet<usize> = cpu::get_state()?.online_cores().into_iter().collect();
+    let mut online_after = online_before.clone();
+    for (core, is_online) in core_states {
+        if *is_online {
+            online_after.insert(*core);
+        } else if *core != 0 {
+            // Core 0 never goes offline
+            online_after.remove(core);
+        }
+    }
+
+    if online_before.is_subset(&online_after) {
+        return Ok(Vec::new());
+    }
+    Ok(affected(read_tasks()?, &online_before, &online_after))
+}
+
+/// Prints the affected processes to stderr.
+pub fn report(tasks: &[PinnedTask]) {
+    for task in tasks {
+        let threads = match task.is_threads() {
+            true => format!(" thread(s) {}", core_list::format(task.tids.iter().map(|tid| *tid as usize))),
+            false => String::new(),
+        };
+        eprintln!(
+            "- PID {} ({}){} allowed on {}: {}",
+            task.pid,
+            task.name,
+            threads,
+            core_list::format(task.allowed.iter().copied()),
+            if task.is_stranded() {
+                "no allowed core remains online, the kernel will break its affinity".to_string()
+            } else {
+                format!("only {} remain online", core_list::format(task.remaining.iter().copied()))
+            }
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(pid: u32, allowed: &str, is_kernel_thread: bool) -> TaskStatus {
+        thread(pid, pid, allowed, is_kernel_thread)
+    }
+
+    fn thread(tid: u32, tgid: u32, allowed: &str, is_kernel_thread: bool) -> TaskStatus {
+        TaskStatus { pid: tid, tgid, name: format!("task{}", tid), allowed: core_list::parse(allowed), is_kernel_thread }
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let status = parse_status("Name:\tredis-server\nPid:\t42\nPPid:\t1\nKthread:\t0\nCpus_allowed_list:\t2-3\n").unwrap();
+        assert_eq!(status.pid, 42);
+        assert_eq!(status.tgid, 42);
+        assert_eq!(status.name, "redis-server");
+        assert_eq!(status.allowed, core_list::parse("2-3"));
+        assert!(!status.is_kernel_thread);
+
+        let status = parse_status("Name:\tksoftirqd/3\nPid:\t30\nPPid:\t2\nCpus_allowed_list:\t3\n").unwrap();
+        assert!(status.is_kernel_thread);
+
+        let status = parse_status("Name:\tworker\nTgid:\t42\nPid:\t45\nPPid:\t1\nCpus_allowed_list:\t3\n").unwrap();
+        assert_eq!((status.pid, status.tgid), (45, 42));
+    }
+
+    #[test]
+    fn test_affected() {
+        let online_before = core_list::parse("0-3");
+        let online_after = core_list::parse("0-1");
+        let tasks = vec![
+            task(1, "0-7", false), // not pinned
+            task(2, "3", false),   // stranded
+            task(3, "1-2", false), // shrinks
+            task(4, "0-1", false), // unaffected
+            task(5, "3", true),    // kernel thread
+        ];
+
+        let affected = affected(tasks, &online_before, &online_after);
+        assert_eq!(affected.iter().map(|task| task.pid).collect::<Vec<u32>>(), vec![2, 3]);
+        assert!(affected[0].is_stranded());
+        assert_eq!(affected[1].remaining, core_list::parse("1"));
+        assert_eq!(affected[0].tids, vec![2]);
+    }
+
+    #[test]
+    fn test_affected_threads() {
+        let online_before = core_list::parse("0-3");
+        let online_after = core_list::parse("0-2");
+        let tasks = vec![
+            thread(10, 10, "0-7", false), // the main thread is not pinned
+            thread(12, 10, "3", false),
+            thread(11, 10, "3", false),
+            thread(13, 10, "2-3", false),
+        ];
+
+        let affected = affected(tasks, &online_before, &online_after);
+        assert_eq!(affected.len(), 2);
+        assert_eq!((affected[0].pid, affected[0].tids.clone()), (10, vec![13]));
+        assert_eq!((affected[1].pid, affected[1].tids.clone()), (10, vec![11, 12]));
+        assert!(affected[1].is_stranded());
+        assert!(affected[1].is_threads());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::core_list;
+use crate::cpu::freq::{self, CoreFreq};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs;
+
+/// `EBUSY`, returned when the EPP can't be changed with the current governor.
+const EBUSY: i32 = 16;
+
+/// A P-state driver with a runtime operation mode, see `Documentation/admin-guide/pm/`.
+struct Driver {
+    name: &'static str,
+    status_file: &'static str,
+    modes: &'static [&'static str],
+}
+
+const DRIVERS: [Driver; 2] = [
+    Driver {
+        name: "intel_pstate",
+        status_file: "/sys/devices/system/cpu/intel_pstate/status",
+        modes: &["active", "passive", "off"],
+    },
+    Driver {
+        name: "amd-pstate",
+        status_file: "/sys/devices/system/cpu/amd_pstate/status",
+        modes: &["active", "passive", "guided", "disable"],
+    },
+];
+
+/// The operation mode of the P-state driver.
+#[derive(Serialize, Debug)]
+pub struct PstateStatus {
+    pub driver: &'static str,
+    /// The current mode, e.g. `active`.
+    pub status: String,
+    /// The modes the driver can be switched to.
+    pub modes: &'static [&'static str],
+}
+
+fn find_driver() -> Option<&'static Driver> {
+    DRIVERS.iter().find(|driver| fs::metadata(driver.status_file).is_ok())
+}
+
+/// Returns the operation mode of the `intel_pstate` or `amd-pstate` driver, `None` if neither is available.
+pub fn status() -> Option<PstateStatus> {
+    let driver = find_driver()?;
+    let status = fs::read_to_string(driver.status_file).ok()?.trim().to_string();
+    Some(PstateStatus { driver: driver.name, status, modes: driver.modes })
+}
+
+/// Switches the P-state driver to `mode`, e.g. `passive` to use the generic cpufreq governors.
+/// Requires root privileges.
+/// # Returns
+/// 1 if the mode changed, 0 if the driver was already in `mode`.
+pub fn set_status(mode: &str) -> Result<usize, Box<dyn Error>> {
+    let driver = find_driver().ok_or("Neither intel_pstate nor amd-pstate is available, their mode can't be set")?;
+    if !driver.modes.contains(&mode) {
+        return Err(Box::from(format!(
+            "Unknown {} mode '{}', available: {}",
+            driver.name,
+            mode,
+            driver.modes.join(", ")
+        )));
+    }
+    let before = status().map(|status| status.status).unwrap_or_default();
+    if before == mode {
+        return Ok(0);
+    }
+    fs::write(driver.status_file, mode)
+        .map_err(|e| format!("Could not write '{}' to {}. {}", mode, driver.status_file, e))?;
+    Ok(1)
+}
+
+/// Checks an energy performance preference against the values a core supports. `intel_pstate`
+/// also accepts a raw EPP value from 0 (performance) to 255 (energy saving).
+fn validate_epp(core: usize, freq: &CoreFreq, epp: &str) -> Result<(), String> {
+    if freq.epp.is_none() {
+        return Err(format!(
+            "Core {} has no energy performance preference, it requires intel_pstate or amd-pstate in active mode",
+            core
+        ));
+    }
+    let is_raw = freq.driver.as_deref() == Some("intel_pstate") && epp.parse::<u8>().is_ok();
+    if is_raw || freq.available_epps.is_empty() || freq.available_epps.iter().any(|available| available == epp) {
+        return Ok(());
+    }
+    Err(format!(
+        "Core {} doesn't support the energy performance preference '{}', available: {}",
+        core,
+        epp,
+        freq.available_epps.join(", ")
+    ))
+}
+
+/// Sets the energy performance preference of `cores`. All the cores are validated before anything
+/// is written, cores without a cpufreq policy (e.g. offline ones) are skipped with a warning.
+/// Requires root privileges.
+/// # Returns
+/// The number of cores whose preference changed.
+pub fn set_epp(cores: &HashSet<usize>, epp: &str) -> Result<usize, Box<dyn Error>> {
+    let mut cores: Vec<usize> = cores.iter().copied().collect();
+    cores.sort_unstable();
+
+    let mut targets = Vec::new();
+    let mut skipped = Vec::new();
+    for core in cores {
+        match freq::read(core) {
+            Some(core_freq) => {
+                validate_epp(core, &core_freq, epp)?;
+                targets.push((core, core_freq));
+            }
+            None => skipped.push(core),
+        }
+    }
+    if targets.is_empty() {
+        return Err(Box::from(format!(
+            "Core(s) {} have no cpufreq policy. {}",
+            core_list::format(skipped),
+            freq::NO_CPUFREQ_MSG
+        )));
+    }
+    if !skipped.is_empty() {
+        eprintln!("Core(s) {} have no cpufreq policy (offline?) and are skipped", core_list::format(skipped));
+    }
+
+    let mut cores_updated = 0;
+    for (core, before) in targets {
+        let path = freq::cpufreq_path(core, "energy_performance_preference");
+        fs::write(&path, epp).map_err(|e| {
+            let hint = if e.raw_os_error() == Some(EBUSY) {
+                ". In active mode, the EPP is fixed to `performance` with the performance governor"
+            } else {
+                ""
+            };
+            format!("Could not write '{}' to {}. {}{}", epp, path, e, hint)
+        })?;
+        let after = freq::read(core).and_then(|core_freq| core_freq.epp);
+        cores_updated += usize::from(after != before.epp);
+    }
+    Ok(cores_updated)
+}
+
+fn render_epp_as_text(frequencies: &BTreeMap<usize, CoreFreq>) {
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!("{:>4}  {:<24}  AVAILABLE", "CORE", "EPP");
+    for (core, core_freq) in frequencies {
+        println!(
+            "{:>4}  {:<24}  {}",
+            core,
+            core_freq.epp.as_deref().unwrap_or("-"),
+            core_freq.available_epps.join(" ")
+        );
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+}
+
+#[derive(Serialize)]
+struct RenderedEpp<'a> {
+    core: usize,
+    epp: Option<&'a str>,
+    available: &'a [String],
+}
+
+/// Renders the energy performance preference of the cores.
+/// # Arguments
+/// * `frequencies` - The cpufreq settings of the cores, see `freq::read_all()`.
+/// * `as_json` - Whether to render in JSON format or in human-readable text format.
+pub fn render_epp(frequencies: &BTreeMap<usize, CoreFreq>, as_json: bool) {
+    if as_json {
+        let rendered: Vec<RenderedEpp> = frequencies
+            .iter()
+            .map(|(core, core_freq)| RenderedEpp {
+                core: *core,
+                epp: core_freq.epp.as_deref(),
+                available: &core_freq.available_epps,
+            })
+            .collect();
+        println!("{}", serde_json::to_string(&rendered).unwrap_or_default());
+    } else if frequencies.values().all(|core_freq| core_freq.epp.is_none()) {
+        println!("No core has an energy performance preference, it requires intel_pstate or amd-pstate in active mode");
+    } else {
+        render_epp_as_text(frequencies);
+    }
+}
+
+/// Renders the operation mode of the P-state driver, see `status()`.
+pub fn render_status(status: Option<&PstateStatus>, as_json: bool) {
+    match (status, as_json) {
+        (status, true) => println!("{}", serde_json::to_string(&status).unwrap_or_default()),
+        (Some(status), false) => {
+            println!("{}: {} (available: {})", status.driver, status.status, status.modes.join(", "))
+        }
+        (None, false) => println!("Neither intel_pstate nor amd-pstate is available"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_epp() {
+        let mut core_freq = CoreFreq {
+            driver: Some("amd-pstate-epp".to_string()),
+            epp: Some("balance_performance".to_string()),
+            available_epps: vec!["performance".to_string(), "balance_performance".to_string(), "power".to_string()],
+            ..Default::default()
+        };
+        assert!(validate_epp(1, &core_freq, "power").is_ok());
+        assert!(validate_epp(1, &core_freq, "turbo").is_err());
+        assert!(validate_epp(1, &core_freq, "128").is_err());
+
+        core_freq.driver = Some("intel_pstate".to_string());
+        assert!(validate_epp(1, &core_freq, "128").is_ok());
+        assert!(validate_epp(1, &core_freq, "256").is_err());
+
+        core_freq.epp = None;
+        assert!(validate_epp(1, &core_freq, "power").is_err());
+    }
+}
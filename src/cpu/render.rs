@@ -1,29 +1,253 @@
-use crate::cpu::cpu_state::CPUState;
+use crate::core_list;
+use crate::cpu::cpu_state::{CPUState, StateDetails};
+use crate::cpu::freq;
+use crate::cpu::get_state::get_details;
+use crate::cpu::pinned::PinnedTask;
+use crate::cpu::report::{self, ChangeResult};
+use crate::cpu::set_state::Outcome;
+use crate::sys_linux;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, IsTerminal};
 use std::string::ToString;
 
-fn render_as_text(state: &CPUState) {
-    println!("―――――――――――――――――――――――――――――――――――――――");
-    println!("CPU CORES");
-    println!("―――――――――――――――――――――――――――――――――――――――");
-    println!("- total:   {}", state.total_cores);
-    println!("- online:  {}", state.cores_online);
-    println!("- offline: {}", state.cores_offline);
-    println!("―――――――――――――――――――――――――――――――――――――――");
+/// The output format of the CPU state.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// JSON following `schema/cores.schema.json`.
+    Json,
+    /// Like `json`, indented.
+    JsonPretty,
+    /// Like `json`, in YAML.
+    Yaml,
+    /// Like `json`, in TOML.
+    Toml,
+    /// The JSON layout from before `schema_version`, as printed with `--json`.
+    Legacy,
+    /// `CORES_TOTAL=8` lines, suitable for `eval` in shell scripts.
+    Env,
+    /// Just the online cores in CPU list format (the offline ones with `--only offline`), e.g. for `taskset -c`.
+    List,
+}
+
+/// The cores to list.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Only {
+    Online,
+    Offline,
+}
+
+/// How to list the cores in text format.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Layout {
+    /// One line per core, or a grid if the cores don't fit on the terminal.
+    #[default]
+    Auto,
+    /// One line per core.
+    List,
+    /// A compact grid of `GRID_COLUMNS` cores per row.
+    Grid,
+}
+
+/// Which cores to list and how.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct View {
+    pub only: Option<Only>,
+    pub layout: Layout,
+}
+
+impl View {
+    /// Whether to list a core that is online (`true`) or offline (`false`).
+    pub fn lists(&self, is_online: bool) -> bool {
+        match self.only {
+            Some(Only::Online) => is_online,
+            Some(Only::Offline) => !is_online,
+            None => true,
+        }
+    }
+}
+
+/// Cores to mark in text format, e.g. the cores that changed while watching the state.
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// Cores to highlight, in reverse video if stdout is a terminal.
+    pub highlighted: HashSet<usize>,
+    /// Notes appended to the line of a core in list layout, e.g. when it last changed.
+    pub notes: HashMap<usize, String>,
+}
+
+impl Marks {
+    /// Highlights `text` if `core` is highlighted and stdout is a terminal.
+    fn apply(&self, core: usize, text: String) -> String {
+        if self.highlighted.contains(&core) && io::stdout().is_terminal() {
+            format!("{}{}{}", HIGHLIGHT, text, RESET)
+        } else {
+            text
+        }
+    }
+}
+
+const HIGHLIGHT: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+/// The number of cores per row of the grid.
+const GRID_COLUMNS: usize = 16;
+
+/// The lines printed around the list of cores, subtracted from the terminal height in auto layout.
+const FRAME_LINES: usize = 12;
+
+/// Whether to render the cores as a grid: in grid layout, or in auto layout if listing `listed`
+/// cores one per line would scroll the terminal. Never in auto layout if stdout is not a terminal.
+fn use_grid(layout: Layout, listed: usize) -> bool {
+    match layout {
+        Layout::Auto => sys_linux::terminal_size().is_some_and(|(rows, _)| listed > rows.saturating_sub(FRAME_LINES)),
+        Layout::List => false,
+        Layout::Grid => true,
+    }
+}
+
+/// Lays out the cores in rows of `GRID_COLUMNS`, `●` for online, `◐` for isolated, `○` for offline
+/// and `·` for the cores not listed, each row labelled with its range of cores.
+fn grid_lines(state: &CPUState, details: &StateDetails, view: &View, marks: &Marks) -> Vec<String> {
+    let last = state.total_cores.saturating_sub(1);
+    let width = format!("{}-{}", last, last).len();
+    state
+        .ordered_core_states
+        .chunks(GRID_COLUMNS)
+        .enumerate()
+        .map(|(row, core_states)| {
+            let first = row * GRID_COLUMNS;
+            let cells: Vec<String> = core_states
+                .iter()
+                .enumerate()
+                .map(|(column, is_online)| {
+                    let cell = match (view.lists(*is_online), *is_online) {
+                        (false, _) => "·",
+                        (true, true) if details.isolated_cores.contains(&(first + column)) => "◐",
+                        (true, true) => "●",
+                        (true, false) => "○",
+                    };
+                    marks.apply(first + column, cell.to_string())
+                })
+                .collect();
+            let range = format!("{}-{}", first, first + core_states.len() - 1);
+            format!("  {:>width$}  {}", range, cells.join(" "), width = width)
+        })
+        .collect()
+}
+
+fn render_list(state: &CPUState, details: &StateDetails, view: &View, marks: &Marks) {
     for (i, core_state) in state.ordered_core_states.iter().enumerate() {
-        let extra = if i == 0 { "(always)" } else { "" };
-        println!(
-            "- [core {}]: {} ",
+        if !view.lists(*core_state) {
+            continue;
+        }
+        let extra = if i == 0 {
+            "(always)"
+        } else if details.isolated_cores.contains(&i) {
+            "(isolated)"
+        } else {
+            ""
+        };
+        let frequency = match details.frequencies.get(&i) {
+            Some(core_freq) if *core_state => freq::summary(core_freq),
+            _ => String::new(),
+        };
+        let line = format!(
+            "- [core {}]: {} {}",
             i,
             if *core_state {
                 format!("on {}", extra)
             } else {
                 "off".to_string()
-            }
+            },
+            frequency
         );
+        let line = match marks.notes.get(&i) {
+            Some(note) => format!("{} {}", line, note),
+            None => line,
+        };
+        println!("{}", marks.apply(i, line));
+    }
+}
+
+fn render_as_text(state: &CPUState, details: &StateDetails, view: &View, marks: &Marks) {
+    let count_with_list = |count: usize, list: String| if count == 0 { count.to_string() } else { format!("{} ({})", count, list) };
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    println!("CPU CORES");
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    println!("- total:   {}", state.total_cores);
+    println!("- online:  {}", count_with_list(state.cores_online, state.online_core_list()));
+    println!("- offline: {}", count_with_list(state.cores_offline, state.offline_core_list()));
+    if !details.isolated_cores.is_empty() {
+        println!("- isolated: {}", core_list::format(details.isolated_cores.iter().copied()));
     }
     println!("―――――――――――――――――――――――――――――――――――――――");
+    let listed = match view.only {
+        Some(Only::Online) => state.cores_online,
+        Some(Only::Offline) => state.cores_offline,
+        None => state.total_cores,
+    };
+    if use_grid(view.layout, listed) {
+        for line in grid_lines(state, details, view, marks) {
+            println!("{}", line);
+        }
+        println!("  ● online  ◐ isolated  ○ offline{}", if view.only.is_some() { "  · not listed" } else { "" });
+    } else {
+        render_list(state, details, view, marks);
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    let notes = details.boot_params.notes();
+    if !notes.is_empty() {
+        println!("BOOT PARAMETERS");
+        println!("―――――――――――――――――――――――――――――――――――――――");
+        for note in notes {
+            println!("- {}", note);
+        }
+        println!("―――――――――――――――――――――――――――――――――――――――");
+    }
+}
+
+fn render_result_as_text(result: &ChangeResult) {
+    println!("CHANGES ({})", result.action);
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    for transition in &result.transitions {
+        let from = transition.from.map_or("-", |from| if from == "online" { "on" } else { "off" });
+        let to = if transition.to == "online" { "on" } else { "off" };
+        match &transition.outcome {
+            Outcome::Changed => println!("- [core {}]: {} → {}", transition.cpu, from, to),
+            Outcome::AlreadyInState => {} // only counted, to keep the list short
+            Outcome::SkippedNotHotpluggable => println!("- [core {}]: skipped, can't be set {}", transition.cpu, to),
+            Outcome::Failed { error, .. } => println!("- [core {}]: failed to set {}, {}", transition.cpu, to, error),
+        }
+    }
+    let totals = &result.totals;
+    println!(
+        "- changed: {}, already in state: {}, skipped: {}, failed: {}",
+        totals.changed, totals.already_in_state, totals.skipped, totals.failed
+    );
+    println!("- online:  {} → {}", result.before.online, result.after.online);
+    println!("―――――――――――――――――――――――――――――――――――――――");
+}
+
+/// The state as shell variable assignments. CPU lists need no quoting, they are empty if there are no such cores.
+fn env_lines(state: &CPUState, details: &StateDetails, result: Option<&ChangeResult>) -> Vec<String> {
+    let mut lines = vec![
+        format!("CORES_TOTAL={}", state.total_cores),
+        format!("CORES_ONLINE={}", state.online_core_list()),
+        format!("CORES_OFFLINE={}", state.offline_core_list()),
+        format!("CORES_ISOLATED={}", core_list::format(details.isolated_cores.iter().copied())),
+    ];
+    if let Some(result) = result {
+        lines.push(format!("CORES_CHANGED={}", result.totals.changed));
+        lines.push(format!("CORES_FAILED={}", result.totals.failed));
+    }
+    lines
 }
 
+/// Renders the state in the JSON layout from before `schema_version`, kept as it was for
+/// compatibility: `cores_online` maps every core to its state, regardless of `--only`.
 fn render_as_json(state: &CPUState) {
 
     let core_states = state
@@ -46,12 +270,96 @@ fn render_as_json(state: &CPUState) {
 /// Renders the CPU state in the desired format.
 /// # Arguments
 /// * `state` - The state of the CPU(s) on the system.
-/// * `as_json` - Whether to render the state in JSON format or in human-readable text format.
+/// * `format` - The output format, see `Format`.
+/// * `view` - Which cores to list (in all formats) and how (in text format).
 ///
-pub fn render(state: &CPUState, as_json: bool) {
-    if as_json {
-        render_as_json(state);
-    } else {
-        render_as_text(state);
+pub fn render(state: &CPUState, format: Format, view: &View) {
+    render_change(state, format, view, &[], None);
+}
+
+/// Renders the CPU state like `render()`, marking cores in text format.
+/// # Arguments
+/// * `marks` - The cores to highlight and the notes to add to their lines, see `Marks`.
+pub fn render_marked(state: &CPUState, format: Format, view: &View, marks: &Marks) {
+    render_all(state, format, view, &[], None, marks);
+}
+
+/// Renders the CPU state after a change, like `render()`, followed by the result of the change,
+/// see `change::apply_transitions()`. In the versioned formats, the processes pinned to
+/// cores taken offline by the change are listed as well. In text format they are reported on
+/// stderr before the change, see `cpu::pinned::report()`.
+pub fn render_change(
+    state: &CPUState,
+    format: Format,
+    view: &View,
+    pinned_tasks: &[PinnedTask],
+    result: Option<&ChangeResult>,
+) {
+    render_all(state, format, view, pinned_tasks, result, &Marks::default());
+}
+
+fn render_all(
+    state: &CPUState,
+    format: Format,
+    view: &View,
+    pinned_tasks: &[PinnedTask],
+    result: Option<&ChangeResult>,
+    marks: &Marks,
+) {
+    let details = match format {
+        Format::List | Format::Legacy => StateDetails::default(), // just the cores
+        _ => get_details(state.total_cores),
+    };
+    match format {
+        Format::Text => {
+            render_as_text(state, &details, view, marks);
+            if let Some(result) = result {
+                render_result_as_text(result);
+            }
+        }
+        Format::Legacy => render_as_json(state),
+        Format::Env => env_lines(state, &details, result).iter().for_each(|line| println!("{}", line)),
+        Format::List if view.only == Some(Only::Offline) => println!("{}", state.offline_core_list()),
+        Format::List => println!("{}", state.online_core_list()),
+        format => match report::to_string(&report::build(state, &details, view, pinned_tasks, result), format) {
+            Ok(output) => println!("{}", output),
+            Err(e) => eprintln!("Could not render the state as {:?}. {}", format, e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_lines() {
+        let mut ordered_core_states = vec![true; 20];
+        ordered_core_states[3] = false;
+        ordered_core_states[17] = false;
+        let state = CPUState {
+            total_cores: 20,
+            cores_online: 18,
+            cores_offline: 2,
+            ordered_core_states,
+        };
+        let details = StateDetails { isolated_cores: [5].into(), ..Default::default() };
+        assert_eq!(
+            grid_lines(&state, &details, &View::default(), &Marks::default()),
+            vec!["   0-15  ● ● ● ○ ● ◐ ● ● ● ● ● ● ● ● ● ●", "  16-19  ● ○ ● ●"]
+        );
+        let view = View { only: Some(Only::Offline), layout: Layout::Grid };
+        assert_eq!(grid_lines(&state, &details, &view, &Marks::default())[1], "  16-19  · ○ · ·");
+    }
+
+    #[test]
+    fn test_env_lines() {
+        let state = CPUState {
+            total_cores: 8,
+            cores_online: 4,
+            cores_offline: 4,
+            ordered_core_states: vec![true, true, true, true, false, false, false, false],
+        };
+        assert_eq!(env_lines(&state, &StateDetails::default(), None), vec!["CORES_TOTAL=8", "CORES_ONLINE=0-3", "CORES_OFFLINE=4-7", "CORES_ISOLATED="]);
     }
 }
@@ -0,0 +1,295 @@
+use crate::core_list;
+use crate::cpu::boot_params::BootParams;
+use crate::cpu::cpu_state::{CPUState, StateDetails};
+use crate::cpu::freq::CoreFreq;
+use crate::cpu::pinned::PinnedTask;
+use crate::cpu::render::{Format, View};
+use crate::cpu::irq::MovedIrq;
+use crate::cpu::set_state::{Outcome, Outcomes, Transition};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The version of the layout of `Report`, see `schema/cores.schema.json`. Incremented whenever a
+/// field is removed, renamed or changes its meaning; adding a field keeps the version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The state of the cores in the machine-readable formats (`--format json|json-pretty|yaml|toml`).
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub schema_version: u32,
+    pub total: usize,
+    pub online_count: usize,
+    pub offline_count: usize,
+    /// The online cores in CPU list format, e.g. `0-3`.
+    pub online: String,
+    /// The offline cores in CPU list format, empty if none.
+    pub offline: String,
+    /// The isolated cores in CPU list format, empty if none.
+    pub isolated: String,
+    /// The cores listed with `--only`, all of them by default.
+    pub cores: Vec<CoreReport>,
+    /// Whether boost (turbo) is enabled, left out if the cpufreq driver has no boost switch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boost: Option<bool>,
+    /// Left out unless boot-time settings affect CPU hotplug or isolation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boot: Option<BootReport>,
+    /// The processes pinned to the cores a change takes offline, left out if none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pinned_tasks: Vec<PinnedTaskReport>,
+    /// What a change did, left out unless the state is printed after a change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ChangeResult>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CoreReport {
+    pub cpu: usize,
+    pub online: bool,
+    pub isolated: bool,
+    /// Left out if the core has no cpufreq policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freq: Option<CoreFreq>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BootReport {
+    /// The relevant parameters of the kernel command line, e.g. `maxcpus = "2"` or `nosmt = ""`.
+    pub cmdline: BTreeMap<String, String>,
+    pub isolated: String,
+    pub nohz_full: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smt_control: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PinnedTaskReport {
+    pub pid: u32,
+    pub name: String,
+    pub tids: Vec<u32>,
+    pub allowed: String,
+    pub remaining: String,
+    pub stranded: bool,
+}
+
+/// The result of a change to the state of the cores: what happened to each core and the online
+/// and offline cores before and after.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChangeResult {
+    /// The change as recorded in the journal, e.g. `set 2`.
+    pub action: String,
+    /// Every core the change tried to set, in ascending order.
+    pub transitions: Vec<Transition>,
+    pub totals: Totals,
+    pub before: StateSummary,
+    pub after: StateSummary,
+    /// The IRQs whose affinity changed, left out if none.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub moved_irqs: Vec<MovedIrq>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    pub attempted: usize,
+    pub changed: usize,
+    pub already_in_state: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StateSummary {
+    pub online: String,
+    pub offline: String,
+}
+
+impl From<&CPUState> for StateSummary {
+    fn from(state: &CPUState) -> StateSummary {
+        StateSummary { online: state.online_core_list(), offline: state.offline_core_list() }
+    }
+}
+
+impl ChangeResult {
+    pub fn new(action: &str, outcomes: Outcomes, before: &CPUState, after: &CPUState) -> ChangeResult {
+        let Outcomes { transitions, moved_irqs } = outcomes;
+        let mut totals = Totals { attempted: transitions.len(), ..Default::default() };
+        for transition in &transitions {
+            match transition.outcome {
+                Outcome::Changed => totals.changed += 1,
+                Outcome::AlreadyInState => totals.already_in_state += 1,
+                Outcome::SkippedNotHotpluggable => totals.skipped += 1,
+                Outcome::Failed { .. } => totals.failed += 1,
+            }
+        }
+        ChangeResult {
+            action: action.to_string(),
+            transitions,
+            totals,
+            before: StateSummary::from(before),
+            after: StateSummary::from(after),
+            moved_irqs,
+        }
+    }
+}
+
+impl From<&BootParams> for BootReport {
+    fn from(boot_params: &BootParams) -> BootReport {
+        BootReport {
+            cmdline: boot_params.cmdline.iter().cloned().collect(),
+            isolated: core_list::format(boot_params.isolated.iter().copied()),
+            nohz_full: core_list::format(boot_params.nohz_full.iter().copied()),
+            smt_control: boot_params.smt_control.clone(),
+        }
+    }
+}
+
+impl From<&PinnedTask> for PinnedTaskReport {
+    fn from(task: &PinnedTask) -> PinnedTaskReport {
+        PinnedTaskReport {
+            pid: task.pid,
+            name: task.name.clone(),
+            tids: task.tids.clone(),
+            allowed: core_list::format(task.allowed.iter().copied()),
+            remaining: core_list::format(task.remaining.iter().copied()),
+            stranded: task.is_stranded(),
+        }
+    }
+}
+
+/// Builds the report of the CPU state, listing the cores according to `view`.
+/// # Arguments
+/// * `state` - The state of the CPU(s) on the system.
+/// * `details` - What is shown along with the state, see `get_state::get_details()`.
+/// * `view` - Which cores to list.
+/// * `pinned_tasks` - The processes pinned to the cores a change takes offline.
+/// * `result` - The result of the change that led to `state`, if any.
+pub fn build(
+    state: &CPUState,
+    details: &StateDetails,
+    view: &View,
+    pinned_tasks: &[PinnedTask],
+    result: Option<&ChangeResult>,
+) -> Report {
+    let cores = state
+        .ordered_core_states
+        .iter()
+        .enumerate()
+        .filter(|(_, is_online)| view.lists(**is_online))
+        .map(|(core, is_online)| CoreReport {
+            cpu: core,
+            online: *is_online,
+            isolated: details.isolated_cores.contains(&core),
+            freq: details.frequencies.get(&core).cloned(),
+        })
+        .collect();
+    Report {
+        schema_version: SCHEMA_VERSION,
+        total: state.total_cores,
+        online_count: state.cores_online,
+        offline_count: state.cores_offline,
+        online: state.online_core_list(),
+        offline: state.offline_core_list(),
+        isolated: core_list::format(details.isolated_cores.iter().copied()),
+        cores,
+        boost: details.boost,
+        boot: details.boot_params.is_relevant().then(|| BootReport::from(&details.boot_params)),
+        pinned_tasks: pinned_tasks.iter().map(PinnedTaskReport::from).collect(),
+        result: result.cloned(),
+    }
+}
+
+/// Serializes `value` in one of the machine-readable formats.
+/// # Returns
+/// The serialized value, without a trailing newline.
+pub fn to_string<T: Serialize>(value: &T, format: Format) -> Result<String, String> {
+    let result = match format {
+        Format::Json => serde_json::to_string(value).map_err(|e| e.to_string()),
+        Format::JsonPretty => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml_ng::to_string(value).map_err(|e| e.to_string()),
+        Format::Toml => toml::to_string(value).map_err(|e| e.to_string()),
+        Format::Text | Format::Legacy | Format::Env | Format::List => Err(format!("{:?} is not a serialization format", format)),
+    };
+    result.map(|text| text.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn sample_state() -> CPUState {
+        CPUState {
+            total_cores: 4,
+            cores_online: 3,
+            cores_offline: 1,
+            ordered_core_states: vec![true, true, true, false],
+        }
+    }
+
+    fn sample_details() -> StateDetails {
+        StateDetails {
+            isolated_cores: [2].into(),
+            boot_params: BootParams { cmdline: vec![("isolcpus".to_string(), "2".to_string())], ..Default::default() },
+            frequencies: BTreeMap::from([(0, CoreFreq { governor: Some("powersave".to_string()), ..Default::default() })]),
+            boost: Some(true),
+        }
+    }
+
+    /// Checks that every property of `value` is described by `schema`, recursively, and that the
+    /// required properties are present.
+    fn check_against_schema(value: &Value, schema: &Value, definitions: &Value, path: &str) {
+        let schema = match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => &definitions[reference.trim_start_matches("#/$defs/")],
+            None => schema,
+        };
+        match value {
+            Value::Object(object) => {
+                let Some(properties) = schema["properties"].as_object() else {
+                    assert!(schema.get("additionalProperties").is_some(), "{} is not an object in the schema", path);
+                    return;
+                };
+                for required in schema["required"].as_array().into_iter().flatten() {
+                    assert!(object.contains_key(required.as_str().unwrap()), "{}.{} is missing", path, required);
+                }
+                for (key, value) in object {
+                    let property = properties.get(key).unwrap_or_else(|| panic!("{}.{} is not in the schema", path, key));
+                    check_against_schema(value, property, definitions, &format!("{}.{}", path, key));
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| check_against_schema(item, &schema["items"], definitions, path)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_report_matches_schema() {
+        let schema: Value = serde_json::from_str(include_str!("../../schema/cores.schema.json")).unwrap();
+        assert_eq!(schema["properties"]["schema_version"]["const"], SCHEMA_VERSION);
+        let task = PinnedTask { pid: 42, name: "bench".to_string(), tids: vec![42, 43], allowed: [3].into(), remaining: [].into() };
+        let transitions = vec![
+            Transition { cpu: 2, from: Some("online"), to: "offline", outcome: Outcome::Changed },
+            Transition { cpu: 3, from: None, to: "offline", outcome: Outcome::Failed { errno: Some(16), error: "busy".to_string() } },
+        ];
+        let moved_irqs = vec![MovedIrq { irq: 24, from: "3".to_string(), to: "0-2".to_string() }];
+        let result = ChangeResult::new("custom 0-1", Outcomes { transitions, moved_irqs }, &sample_state(), &sample_state());
+        let report = serde_json::to_value(build(&sample_state(), &sample_details(), &View::default(), &[task], Some(&result))).unwrap();
+        check_against_schema(&report, &schema, &schema["$defs"], "$");
+        assert_eq!(report["offline"], "3");
+        assert_eq!(report["cores"][0]["freq"]["governor"], "powersave");
+        assert_eq!(report["result"]["transitions"][1]["outcome"], "failed");
+        assert_eq!(report["result"]["transitions"][1]["errno"], 16);
+        assert_eq!(report["result"]["totals"]["changed"], 1);
+        assert_eq!(report["result"]["moved_irqs"][0]["to"], "0-2");
+    }
+
+    #[test]
+    fn test_to_string() {
+        let report = build(&sample_state(), &sample_details(), &View { only: Some(crate::cpu::render::Only::Offline), ..Default::default() }, &[], None);
+        let toml = to_string(&report, Format::Toml).unwrap();
+        assert!(toml.starts_with("schema_version = 1\n"));
+        assert!(toml.contains("[[cores]]\ncpu = 3\nonline = false"));
+        let yaml = to_string(&report, Format::Yaml).unwrap();
+        assert!(yaml.contains("cores:\n- cpu: 3\n  online: false"));
+        assert!(to_string(&report, Format::Legacy).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::core_list;
+use crate::journal::JOURNAL_DIR;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::str::FromStr;
+
+/// The cores assigned to each of a set of items, e.g. the CPU affinity of IRQs or the cpus of cpusets.
+pub type CoreSets<K> = BTreeMap<K, HashSet<usize>>;
+
+/// Cores assigned to items before `cores` took some of them offline, kept in a file under
+/// `/var/lib/cores/` so that they can be restored when the cores come back online, possibly
+/// by a later run of `cores`. On disk every item is a line `<item>\t<CPU list>`.
+pub struct SavedCoreSets {
+    pub file: &'static str,
+}
+
+impl SavedCoreSets {
+    pub fn read<K: FromStr + Ord>(&self) -> CoreSets<K> {
+        fs::read_to_string(self.file).map_or_else(|_| CoreSets::new(), |content| parse(&content))
+    }
+
+    fn write<K: Display>(&self, saved: &CoreSets<K>) {
+        let result = if saved.is_empty() {
+            fs::remove_file(self.file).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(e),
+            })
+        } else {
+            fs::create_dir_all(JOURNAL_DIR).and_then(|_| fs::write(self.file, format(saved)))
+        };
+        if let Err(e) = result {
+            eprintln!("Could not save the state in {}. {}", self.file, e);
+        }
+    }
+
+    /// Saves the cores of the items assigned to any of the cores going offline. Items saved by an
+    /// earlier change keep their original cores. Requires root privileges.
+    /// # Arguments
+    /// * `current` - The current cores of all the items.
+    /// * `going_offline` - The cores about to be taken offline.
+    pub fn save<K: FromStr + Display + Ord + Clone>(&self, current: &CoreSets<K>, going_offline: &HashSet<usize>) {
+        if going_offline.is_empty() {
+            return;
+        }
+        let mut saved: CoreSets<K> = self.read();
+        let saved_before = saved.len();
+        for (item, cores) in current {
+            if !cores.is_disjoint(going_offline) {
+                saved.entry(item.clone()).or_insert_with(|| cores.clone());
+            }
+        }
+        if saved.len() != saved_before {
+            self.write(&saved);
+        }
+    }
+
+    /// Decides which saved items to restore after cores came back online, and forgets the items that
+    /// need no further restoring. Requires root privileges.
+    /// # Arguments
+    /// * `current` - The current cores of all the items.
+    /// * `back_online` - The cores brought back online.
+    /// * `online` - All the cores online now.
+    /// # Returns
+    /// The cores to assign to the items whose original cores include cores back online, limited to the online cores.
+    pub fn restore<K: FromStr + Display + Ord + Clone>(
+        &self,
+        current: &CoreSets<K>,
+        back_online: &HashSet<usize>,
+        online: &HashSet<usize>,
+    ) -> CoreSets<K> {
+        if back_online.is_empty() {
+            return CoreSets::new();
+        }
+        let mut saved: CoreSets<K> = self.read();
+        let (to_write, done) = plan_restore(&saved, current, back_online, online);
+        for item in &done {
+            saved.remove(item);
+        }
+        if !done.is_empty() {
+            self.write(&saved);
+        }
+        to_write
+    }
+}
+
+fn parse<K: FromStr + Ord>(content: &str) -> CoreSets<K> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (item, cpu_list) = line.rsplit_once('\t')?;
+            Some((item.parse().ok()?, core_list::parse_or_empty(cpu_list)))
+        })
+        .collect()
+}
+
+fn format<K: Display>(saved: &CoreSets<K>) -> String {
+    saved
+        .iter()
+        .map(|(item, cores)| format!("{}\t{}\n", item, core_list::format(cores.iter().copied())))
+        .collect()
+}
+
+/// # Returns
+/// The cores to write, limited to the online cores, and the items whose original cores are fully
+/// online again (or that are gone) and no longer need to be kept.
+fn plan_restore<K: Ord + Clone>(
+    saved: &CoreSets<K>,
+    current: &CoreSets<K>,
+    back_online: &HashSet<usize>,
+    online: &HashSet<usize>,
+) -> (CoreSets<K>, Vec<K>) {
+    let mut to_write = CoreSets::new();
+    let mut done = Vec::new();
+    for (item, original) in saved {
+        let Some(cores) = current.get(item) else {
+            done.push(item.clone()); // the item is gone, e.g. the driver was unloaded or the cgroup removed
+            continue;
+        };
+        if original.is_disjoint(back_online) {
+            continue;
+        }
+        let target: HashSet<usize> = original.intersection(online).copied().collect();
+        if cores != &target {
+            to_write.insert(item.clone(), target);
+        }
+        if original.is_subset(online) {
+            done.push(item.clone());
+        }
+    }
+    (to_write, done)
+}
+
+/// Lists the items whose cores changed from `before` to `after`.
+/// # Returns
+/// Each item with its cores before and after.
+pub fn changes<'a, K: Ord>(
+    before: &'a CoreSets<K>,
+    after: &'a CoreSets<K>,
+) -> Vec<(&'a K, &'a HashSet<usize>, &'a HashSet<usize>)> {
+    before
+        .iter()
+        .filter_map(|(item, cores_before)| match after.get(item) {
+            Some(cores_after) if cores_after != cores_before => Some((item, cores_before, cores_after)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reports the items whose cores changed from `before` to `after` on stderr.
+/// # Arguments
+/// * `describe` - Describes an item, e.g. `IRQ 42`.
+pub fn report_changes<K: Ord>(before: &CoreSets<K>, after: &CoreSets<K>, describe: impl Fn(&K) -> String) {
+    for (item, cores_before, cores_after) in changes(before, after) {
+        eprintln!(
+            "{} moved from CPU(s) {} to {}",
+            describe(item),
+            core_list::format(cores_before.iter().copied()),
+            core_list::format(cores_after.iter().copied())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let saved = CoreSets::from([(24, core_list::parse("2-3")), (31, core_list::parse("0,5"))]);
+        assert_eq!(parse::<u32>(&format(&saved)), saved);
+
+        let saved = CoreSets::from([("/sys/fs/cgroup/cpuset/docker".to_string(), core_list::parse("0-3"))]);
+        assert_eq!(parse::<String>(&format(&saved)), saved);
+    }
+
+    #[test]
+    fn test_plan_restore() {
+        let saved = CoreSets::from([
+            (24, core_list::parse("2-3")), // core 3 back online, core 2 still offline
+            (25, core_list::parse("5")),   // core 5 still offline
+            (26, core_list::parse("3")),   // already restored
+            (27, core_list::parse("3")),   // the item is gone
+        ]);
+        let current = CoreSets::from([
+            (24, core_list::parse("0-1")),
+            (25, core_list::parse("0-1")),
+            (26, core_list::parse("3")),
+        ]);
+        let (to_write, done) = plan_restore(&saved, &current, &core_list::parse("3"), &core_list::parse("0-1,3"));
+
+        assert_eq!(to_write, CoreSets::from([(24, core_list::parse("3"))]));
+        assert_eq!(done, vec![26, 27]);
+    }
+}
@@ -1,108 +1,182 @@
 use crate::cpu;
+use crate::cpu::boot_params::{self, BootParams};
 use crate::cpu::cpu_state::CPUState;
-use std;
-use std::collections::HashMap;
+use crate::cpu::cpuset;
+use crate::cpu::irq::{self, MovedIrq};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::path::Path;
+
+/// What happened to a core in a change.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    Changed,
+    AlreadyInState,
+    /// Core 0, or a core without `cpuN/online`.
+    SkippedNotHotpluggable,
+    /// The kernel refused the change, e.g. with `EBUSY`. `errno` is `None` for cores that don't exist.
+    Failed { errno: Option<i32>, error: String },
+}
 
-/// Sets the state of a specified core.
-pub fn set_core_state(core: usize, is_online: bool) -> Result<bool, Box<dyn std::error::Error>> {
-    // Don't ever change state of the core 0.
-    if core == 0 {
-        return Ok(false);
-    }
+/// An attempt to set the state of a core.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub cpu: usize,
+    /// `online` or `offline`, `None` if the core doesn't exist.
+    pub from: Option<&'static str>,
+    pub to: &'static str,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
 
-    let dest_state = if is_online { "1" } else { "0" };
-    let cpu_state_path = format!("/sys/devices/system/cpu/cpu{}/online", core);
+/// What a change of the state of the cores did.
+#[derive(Debug, Default)]
+pub struct Outcomes {
+    /// Every core the change tried to set, in ascending order.
+    pub transitions: Vec<Transition>,
+    /// The IRQs the kernel moved to other cores or `cores` moved back, see `keeping_affinities()`.
+    pub moved_irqs: Vec<MovedIrq>,
+}
 
-    let old_cpu_state: CPUState = cpu::get_state()?;
-    if core > old_cpu_state.total_cores-1 {
-        eprintln!("Core {} does not exist", core);
-        return Ok(false);
+pub fn state_name(is_online: bool) -> &'static str {
+    if is_online {
+        "online"
+    } else {
+        "offline"
     }
+}
 
-    if old_cpu_state.ordered_core_states[core] == is_online {
-        return Ok(false);
-    }
+/// Counts the cores whose state was changed.
+pub fn count_changed(transitions: &[Transition]) -> usize {
+    transitions.iter().filter(|transition| transition.outcome == Outcome::Changed).count()
+}
 
-    let mut core_state_updated: bool = false;
+/// Sets the state of `core`, unless it is already in that state or can't be changed. Failures are
+/// reported on stderr as well.
+/// # Arguments
+/// * `old_cpu_state` - The state of the cores before the change.
+/// * `boot_params` - The boot parameters, read once per change to explain failures.
+fn transition(core: usize, is_online: bool, old_cpu_state: &CPUState, boot_params: &BootParams) -> Transition {
+    let dest_state = if is_online { "1" } else { "0" };
+    let cpu_state_path = format!("/sys/devices/system/cpu/cpu{}/online", core);
 
-    // Attempt to enable the core by writing "1" to the corresponding CPU file
-    match fs::write(cpu_state_path, dest_state) {
-        Ok(_) => {
-            core_state_updated = true;
-        }
-        Err(e) => {
-            eprintln!("Could not set core {} to state {}. {}", core, dest_state, e);
+    let outcome = match old_cpu_state.ordered_core_states.get(core) {
+        None => {
+            match boot_params.explain_missing() {
+                Some(explanation) => eprintln!("Core {} does not exist. {}", core, explanation),
+                None => eprintln!("Core {} does not exist", core),
+            }
+            Outcome::Failed { errno: None, error: format!("core {} does not exist", core) }
         }
+        Some(old_state) if *old_state == is_online => Outcome::AlreadyInState,
+        // Don't ever change state of the core 0.
+        Some(_) if core == 0 || !Path::new(&cpu_state_path).exists() => Outcome::SkippedNotHotpluggable,
+        // Attempt to change the state by writing "1" or "0" to the corresponding CPU file
+        Some(_) => match fs::write(&cpu_state_path, dest_state) {
+            Ok(_) => Outcome::Changed,
+            Err(e) => {
+                match boot_params.explain_failure(is_online, &e) {
+                    Some(explanation) => eprintln!("Could not set core {} to state {}. {}. {}", core, dest_state, e, explanation),
+                    None => eprintln!("Could not set core {} to state {}. {}", core, dest_state, e),
+                }
+                Outcome::Failed { errno: e.raw_os_error(), error: e.to_string() }
+            }
+        },
+    };
+    Transition {
+        cpu: core,
+        from: old_cpu_state.ordered_core_states.get(core).map(|old_state| state_name(*old_state)),
+        to: state_name(is_online),
+        outcome,
     }
-    Ok(core_state_updated)
+}
+
+/// Sets the state of a specified core.
+/// IRQ affinities and cpusets are kept across offline/online cycles, see `keeping_affinities()`.
+pub fn set_core_state(core: usize, is_online: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(set_cores(&HashMap::from([(core, is_online)]))? == 1)
+}
+
+/// Runs `change`, saving the affinity of the IRQs and the cpus of the cpusets the kernel strips of the
+/// cores going offline, and restoring those whose original cores come back online. IRQs and cpusets
+/// that changed are reported on stderr.
+/// # Arguments
+/// * `going_offline` - The cores that `change` takes offline.
+/// * `change` - The function that changes the state.
+/// # Returns
+/// The result of `change` and the IRQs whose affinity changed.
+fn keeping_affinities<F, T>(going_offline: &HashSet<usize>, change: F) -> Result<(T, Vec<MovedIrq>), Box<dyn std::error::Error>>
+where
+    F: FnOnce() -> T,
+{
+    let irqs_before = irq::read_affinities();
+    irq::save(&irqs_before, going_offline);
+    let cpusets_before = cpuset::save(going_offline);
+    let online_before: HashSet<usize> = cpu::get_state()?.online_cores().into_iter().collect();
+
+    let result = change();
+
+    let online_after: HashSet<usize> = cpu::get_state()?.online_cores().into_iter().collect();
+    let back_online: HashSet<usize> = online_after.difference(&online_before).copied().collect();
+    cpuset::restore(&cpusets_before, &back_online, &online_after);
+    irq::restore(&back_online, &online_after);
+    cpuset::report_changes(&cpusets_before);
+    let moved_irqs = irq::report_moved(&irqs_before);
+
+    Ok((result, moved_irqs))
 }
 
 /// Sets the state of the cores as specified in the `core_states` HashMap.
+/// IRQ affinities and cpusets are kept across offline/online cycles, see `keeping_affinities()`.
 /// # Arguments
 /// * `core_states` - A HashMap with the core index as the key and the desired state as the value.
+/// # Returns
+/// The number of cores updated.
 pub fn set_cores(core_states: &HashMap<usize, bool>) -> Result<usize, Box<dyn std::error::Error>> {
-    let old_cpu_state: CPUState = cpu::get_state()?;
-
-    let mut core_states_updated: usize = 0;
-
-    core_states.iter().for_each(|(core, state)| {
-        // Don't ever change state of the core 0.
-        if *core == 0 {
-            return;
-        }
+    Ok(count_changed(&set_cores_with_outcomes(core_states)?.transitions))
+}
 
-        // The given core is already in the desired state
-        if old_cpu_state.ordered_core_states[*core] == *state {
-            return;
-        }
-        match set_core_state(*core, *state) {
-            Ok(result) => {
-                if result{
-                    core_states_updated += 1;
-                }
-            }
-            Err(e) => {
-                eprintln!("Could not set core {} to state {}. {}", core, state, e);
-            }
-        }
-    });
+/// Sets the state of the cores like `set_cores()`.
+/// # Returns
+/// What happened to each core of `core_states`, in ascending order of the cores, and to the IRQs.
+pub fn set_cores_with_outcomes(core_states: &HashMap<usize, bool>) -> Result<Outcomes, Box<dyn std::error::Error>> {
+    let old_cpu_state: CPUState = cpu::get_state()?;
+    let boot_params = boot_params::get();
 
-    Ok(core_states_updated)
+    let going_offline: HashSet<usize> = core_states
+        .iter()
+        .filter(|(core, state)| **core != 0 && !**state && old_cpu_state.ordered_core_states.get(**core) == Some(&true))
+        .map(|(core, _)| *core)
+        .collect();
+
+    let mut core_states: Vec<(usize, bool)> = core_states.iter().map(|(core, state)| (*core, *state)).collect();
+    core_states.sort_unstable();
+    let (transitions, moved_irqs) = keeping_affinities(&going_offline, || {
+        core_states.iter().map(|(core, state)| transition(*core, *state, &old_cpu_state, &boot_params)).collect()
+    })?;
+    Ok(Outcomes { transitions, moved_irqs })
 }
 
 /// Resets all cores to online state.
+/// IRQs and cpusets stripped of the cores when they went offline get them back, see `keeping_affinities()`.
 ///
 /// # Returns
 /// The number of cores that were brought online.
 pub fn reset_cores() -> Result<usize, Box<dyn std::error::Error>> {
-    let old_cpu_state: CPUState = cpu::get_state()?;
-
-    let mut core_states_updated: usize = 0;
-
-    old_cpu_state
-        .ordered_core_states
-        .iter()
-        .enumerate()
-        .for_each(|(core, state)| {
-            // Don't ever change state of the core 0.
-            if core == 0 {
-                return;
-            }
+    Ok(count_changed(&reset_cores_with_outcomes()?.transitions))
+}
 
-            if !*state {
-                match set_core_state(core, true) {
-                    Ok( result ) => {
-                        if result{
-                            core_states_updated += 1;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Could not bring core {} online. {}.", core, e);
-                    }
-                }
-            }
-        });
+/// Resets all cores to online state like `reset_cores()`.
+/// # Returns
+/// What happened to each core, in ascending order of the cores, and to the IRQs.
+pub fn reset_cores_with_outcomes() -> Result<Outcomes, Box<dyn std::error::Error>> {
+    let old_cpu_state: CPUState = cpu::get_state()?;
+    let boot_params = boot_params::get();
 
-    Ok(core_states_updated)
+    let (transitions, moved_irqs) = keeping_affinities(&HashSet::new(), || {
+        (0..old_cpu_state.total_cores).map(|core| transition(core, true, &old_cpu_state, &boot_params)).collect()
+    })?;
+    Ok(Outcomes { transitions, moved_irqs })
 }
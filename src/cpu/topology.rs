@@ -0,0 +1,153 @@
+use crate::core_list;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+/// Where a core sits in the system, as in `/sys/devices/system/cpu/cpuN/topology/`. The kernel
+/// removes the topology of offline cores on most architectures, then only the NUMA node is known.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct CoreTopology {
+    /// The physical package (socket).
+    pub package: Option<usize>,
+    /// The physical core within the package, shared by the SMT siblings.
+    pub core_id: Option<usize>,
+    pub node: Option<usize>,
+    /// The SMT siblings sharing the physical core, including the core itself.
+    pub siblings: Vec<usize>,
+}
+
+fn topology_path(core: usize, file: &str) -> String {
+    format!("/sys/devices/system/cpu/cpu{}/topology/{}", core, file)
+}
+
+fn read_number(path: &str) -> Option<usize> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Finds the NUMA node of a core from its `cpuN/nodeK` link.
+fn read_node(core: usize) -> Option<usize> {
+    fs::read_dir(format!("/sys/devices/system/cpu/cpu{}", core))
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_prefix("node")?.parse().ok())
+        .next()
+}
+
+/// Reads the topology of `core`.
+pub fn get(core: usize) -> CoreTopology {
+    let mut siblings: Vec<usize> = fs::read_to_string(topology_path(core, "thread_siblings_list"))
+        .map_or_else(|_| Default::default(), |content| core_list::parse_or_empty(&content))
+        .into_iter()
+        .collect();
+    siblings.sort_unstable();
+    CoreTopology {
+        package: read_number(&topology_path(core, "physical_package_id")),
+        core_id: read_number(&topology_path(core, "core_id")),
+        node: read_node(core),
+        siblings,
+    }
+}
+
+/// Reads the topology of all the `total_cores` cores.
+pub fn get_all(total_cores: usize) -> BTreeMap<usize, CoreTopology> {
+    (0..total_cores).map(|core| (core, get(core))).collect()
+}
+
+/// Keeps the topology of cores that went offline, whose topology the kernel removed, from when they
+/// were online, so that they keep their place among their package and SMT siblings.
+/// # Arguments
+/// * `topologies` - The topology of the cores just read, see `get_all()`.
+/// * `known` - The topology of the cores read before.
+pub fn fill_unknown(topologies: &mut BTreeMap<usize, CoreTopology>, known: &BTreeMap<usize, CoreTopology>) {
+    for (core, topology) in topologies.iter_mut() {
+        if topology.package.is_some() && topology.core_id.is_some() {
+            continue;
+        }
+        if let Some(known_topology) = known.get(core).filter(|known| known.package.is_some() && known.core_id.is_some()) {
+            *topology = known_topology.clone();
+        }
+    }
+}
+
+/// The PMUs of hybrid CPUs (e.g. Intel Alder Lake) listing their performance and efficiency cores.
+const HYBRID_CORE_TYPES: [(&str, &str); 2] =
+    [("/sys/devices/cpu_core/cpus", "performance"), ("/sys/devices/cpu_atom/cpus", "efficiency")];
+
+/// Reads the type of each core of a hybrid CPU.
+/// # Returns
+/// `performance` or `efficiency` for each core, empty if the CPU is not hybrid.
+pub fn core_types() -> HashMap<usize, &'static str> {
+    HYBRID_CORE_TYPES
+        .iter()
+        .flat_map(|(path, core_type)| {
+            let cores = fs::read_to_string(path).map_or_else(|_| Default::default(), |content| core_list::parse_or_empty(&content));
+            cores.into_iter().map(move |core| (core, *core_type))
+        })
+        .collect()
+}
+
+/// Groups the cores by package and physical core, the SMT siblings of a physical core together.
+/// Cores with an unknown topology (e.g. offline) come last, in a group of their own each.
+/// # Returns
+/// The packages in ascending order (`None` for the unknown ones) with their physical cores, each a list of cores.
+pub fn group(topologies: &BTreeMap<usize, CoreTopology>) -> Vec<(Option<usize>, Vec<Vec<usize>>)> {
+    let mut known: BTreeMap<usize, BTreeMap<usize, Vec<usize>>> = BTreeMap::new();
+    let mut unknown = Vec::new();
+    for (core, topology) in topologies {
+        match (topology.package, topology.core_id) {
+            (Some(package), Some(core_id)) => known.entry(package).or_default().entry(core_id).or_default().push(*core),
+            _ => unknown.push(vec![*core]),
+        }
+    }
+    let mut groups: Vec<(Option<usize>, Vec<Vec<usize>>)> = known
+        .into_iter()
+        .map(|(package, physical_cores)| {
+            let mut physical_cores: Vec<Vec<usize>> = physical_cores.into_values().collect();
+            physical_cores.sort_by_key(|cores| cores[0]);
+            (Some(package), physical_cores)
+        })
+        .collect();
+    if !unknown.is_empty() {
+        groups.push((None, unknown));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let topology = |package, core_id| CoreTopology { package, core_id, ..Default::default() };
+        let topologies = BTreeMap::from([
+            (0, topology(Some(0), Some(0))),
+            (1, topology(Some(0), Some(1))),
+            (2, topology(Some(1), Some(0))),
+            (3, topology(None, None)), // offline
+            (4, topology(Some(0), Some(0))),
+            (5, topology(Some(0), Some(1))),
+        ]);
+        assert_eq!(
+            group(&topologies),
+            vec![
+                (Some(0), vec![vec![0, 4], vec![1, 5]]),
+                (Some(1), vec![vec![2]]),
+                (None, vec![vec![3]]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_unknown() {
+        let topology = |package, core_id| CoreTopology { package, core_id, ..Default::default() };
+        let known = BTreeMap::from([(0, topology(Some(0), Some(0))), (1, topology(Some(0), Some(1)))]);
+        let mut topologies = BTreeMap::from([
+            (0, topology(Some(0), Some(0))),
+            (1, topology(None, None)), // went offline
+            (2, topology(None, None)), // offline since before
+        ]);
+        fill_unknown(&mut topologies, &known);
+        assert_eq!(topologies[&1], topology(Some(0), Some(1)));
+        assert_eq!(topologies[&2], topology(None, None));
+    }
+}
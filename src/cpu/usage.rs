@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::fs;
+
+const PROC_STAT: &str = "/proc/stat";
+
+/// The time each core spent busy and in total since boot, in clock ticks, from `/proc/stat`.
+pub type CpuTimes = HashMap<usize, (u64, u64)>;
+
+/// Parses the `cpuN` lines of `/proc/stat`: user, nice, system, idle, iowait, irq, softirq and steal
+/// time. Guest time is already included in user time.
+fn parse_stat(content: &str) -> CpuTimes {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let core = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let times: Vec<u64> = fields.take(8).filter_map(|field| field.parse().ok()).collect();
+            let total: u64 = times.iter().sum();
+            let idle = times.get(3).copied().unwrap_or_default() + times.get(4).copied().unwrap_or_default();
+            Some((core, (total - idle, total)))
+        })
+        .collect()
+}
+
+/// Reads the busy and total time of the online cores.
+pub fn sample() -> CpuTimes {
+    fs::read_to_string(PROC_STAT).map_or_else(|_| CpuTimes::new(), |content| parse_stat(&content))
+}
+
+/// Computes the utilization of each core between two samples.
+/// # Returns
+/// The utilization in percent of the cores online in both samples.
+pub fn utilization(before: &CpuTimes, after: &CpuTimes) -> HashMap<usize, f64> {
+    after
+        .iter()
+        .filter_map(|(core, (busy_after, total_after))| {
+            let (busy_before, total_before) = before.get(core)?;
+            let total = total_after.checked_sub(*total_before).filter(|total| *total > 0)?;
+            let busy = busy_after.saturating_sub(*busy_before);
+            Some((*core, (busy as f64 * 100.0 / total as f64).min(100.0)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utilization() {
+        let before = parse_stat("cpu  10 0 10 80 0 0 0 0 0 0\ncpu0 5 0 5 40 0 0 0 0 0 0\ncpu1 5 0 5 40 0 0 0 0 0 0\n");
+        let after = parse_stat("cpu  40 0 10 150 0 0 0 0 0 0\ncpu0 35 0 5 60 0 0 0 0 0 0\ncpu1 5 0 5 90 0 0 0 0 0 0\n");
+        assert_eq!(before.get(&0), Some(&(10, 50)));
+        let utilization = utilization(&before, &after);
+        assert_eq!(utilization.get(&0), Some(&60.0));
+        assert_eq!(utilization.get(&1), Some(&0.0));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::core_list;
+use crate::cpu;
+use crate::cpu::boot_params::LINUX_SMT_CONTROL;
+use crate::cpu::cpu_state::CPUState;
+use crate::cpu::cpuset::PROC_MOUNTS;
+use crate::cpu::hotplug::LINUX_HOTPLUG_STATES;
+use crate::sys_linux::{self, DropPrivilegeResult};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+const PROC_CPUINFO: &str = "/proc/cpuinfo";
+const PROC_INIT_CGROUP: &str = "/proc/1/cgroup";
+const HYPERVISOR_TYPE: &str = "/sys/hypervisor/type";
+const DMI_SYS_VENDOR: &str = "/sys/class/dmi/id/sys_vendor";
+const DMI_PRODUCT_NAME: &str = "/sys/class/dmi/id/product_name";
+
+/// Reported when the CPU flags tell we're in a VM, but nothing tells which hypervisor.
+const UNKNOWN_HYPERVISOR: &str = "unknown";
+
+/// The outcome of a check.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A check of the environment `cores` runs in.
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Check { name, status, detail: detail.into() }
+    }
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok().map(|content| content.trim().to_string())
+}
+
+/// Whether the kernel supports CPU hotplug at all (`CONFIG_HOTPLUG_CPU`).
+fn check_kernel_hotplug(state: &CPUState) -> Check {
+    let name = "kernel hotplug";
+    let has_online_files = (1..state.total_cores)
+        .any(|core| Path::new(&format!("/sys/devices/system/cpu/cpu{}/online", core)).exists());
+    if Path::new(LINUX_HOTPLUG_STATES).exists() || has_online_files {
+        Check::new(name, Status::Pass, format!("supported, {} core(s) present, {} online", state.total_cores, state.cores_online))
+    } else if state.total_cores > 1 {
+        Check::new(name, Status::Fail, "the kernel was built without CPU hotplug support (CONFIG_HOTPLUG_CPU)")
+    } else {
+        Check::new(name, Status::Warn, "no hotplug support detected, but only core 0 is present anyway")
+    }
+}
+
+/// Which cores have `cpuN/online` and can be taken offline.
+fn check_hotpluggable(state: &CPUState, hotpluggable: &[usize]) -> Check {
+    let name = "hotpluggable cores";
+    let fixed: Vec<usize> = (1..state.total_cores).filter(|core| !hotpluggable.contains(core)).collect();
+    if state.total_cores <= 1 {
+        Check::new(name, Status::Warn, "only core 0 is present, there is nothing to take offline")
+    } else if hotpluggable.is_empty() {
+        Check::new(name, Status::Fail, format!("none of the cores 1-{} can be taken offline", state.total_cores - 1))
+    } else if !fixed.is_empty() {
+        Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "cores {} can be taken offline, cores {} can't (no cpuN/online)",
+                core_list::format(hotpluggable.iter().copied()),
+                core_list::format(fixed)
+            ),
+        )
+    } else {
+        Check::new(name, Status::Pass, format!("cores {} can be taken offline", core_list::format(hotpluggable.iter().copied())))
+    }
+}
+
+/// Returns whether the filesystem mounted last at `mount_point` is read-only, according to `/proc/mounts`.
+fn is_read_only(mounts: &str, mount_point: &str) -> Option<bool> {
+    mounts.lines().rev().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if *fields.get(1)? != mount_point {
+            return None;
+        }
+        Some(fields.get(3).is_some_and(|options| options.split(',').any(|option| option == "ro")))
+    })
+}
+
+/// Whether sysfs is mounted read-write and `cpuN/online` is writable (by root).
+fn check_sysfs(hotpluggable: &[usize]) -> Check {
+    let name = "sysfs writable";
+    let mounts = fs::read_to_string(PROC_MOUNTS).unwrap_or_default();
+    match is_read_only(&mounts, "/sys") {
+        Some(true) => return Check::new(name, Status::Fail, "/sys is mounted read-only, e.g. in an unprivileged container"),
+        None => return Check::new(name, Status::Fail, "sysfs is not mounted at /sys"),
+        Some(false) => {}
+    }
+    let Some(core) = hotpluggable.first() else {
+        return Check::new(name, Status::Pass, "/sys is mounted read-write");
+    };
+    let online_file = format!("/sys/devices/system/cpu/cpu{}/online", core);
+    match fs::metadata(&online_file) {
+        Ok(metadata) if metadata.permissions().readonly() => {
+            Check::new(name, Status::Fail, format!("{} is read-only", online_file))
+        }
+        Ok(_) => Check::new(name, Status::Pass, "/sys is mounted read-write, cpuN/online is writable by root"),
+        Err(e) => Check::new(name, Status::Fail, format!("Could not access {}. {}", online_file, e)),
+    }
+}
+
+/// Detects the container runtime from its marker files and the cgroup of the init process.
+fn detect_container(dockerenv: bool, containerenv: bool, init_cgroup: &str) -> Option<&'static str> {
+    if dockerenv {
+        return Some("Docker");
+    }
+    if containerenv {
+        return Some("Podman");
+    }
+    [("kubepods", "Kubernetes"), ("docker", "Docker"), ("libpod", "Podman"), ("lxc", "LXC")]
+        .into_iter()
+        .find(|(marker, _)| init_cgroup.lines().any(|line| line.contains(marker)))
+        .map(|(_, runtime)| runtime)
+}
+
+/// Detects the hypervisor from `/sys/hypervisor/type`, the DMI system vendor and product name, and
+/// the `hypervisor` CPU flag, which is set in any VM on x86 but doesn't tell which hypervisor.
+fn detect_hypervisor(cpuinfo: &str, hypervisor_type: Option<&str>, vendor: &str, product: &str) -> Option<String> {
+    if let Some(hypervisor_type) = hypervisor_type.filter(|hypervisor_type| !hypervisor_type.is_empty()) {
+        return Some(hypervisor_type.to_string());
+    }
+    let known = [
+        ("QEMU", "QEMU/KVM"),
+        ("VMware", "VMware"),
+        ("innotek", "VirtualBox"),
+        ("Xen", "Xen"),
+        ("Amazon EC2", "Amazon EC2"),
+        ("Google", "Google Compute Engine"),
+        ("Parallels", "Parallels"),
+        ("BHYVE", "bhyve"),
+    ];
+    if let Some((_, hypervisor)) = known.iter().find(|(marker, _)| vendor.contains(marker) || product.contains(marker)) {
+        return Some(hypervisor.to_string());
+    }
+    if vendor.contains("Microsoft") && product.contains("Virtual Machine") {
+        return Some("Hyper-V".to_string());
+    }
+    let has_flag = cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("flags"))
+        .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"));
+    has_flag.then(|| UNKNOWN_HYPERVISOR.to_string())
+}
+
+/// Whether `cores` runs in a container or a VM, where changes have a different scope.
+fn check_virtualization() -> Check {
+    let name = "virtualization";
+    let container = detect_container(
+        Path::new("/.dockerenv").exists(),
+        Path::new("/run/.containerenv").exists(),
+        &fs::read_to_string(PROC_INIT_CGROUP).unwrap_or_default(),
+    );
+    let hypervisor = detect_hypervisor(
+        &fs::read_to_string(PROC_CPUINFO).unwrap_or_default(),
+        read_trimmed(HYPERVISOR_TYPE).as_deref(),
+        &read_trimmed(DMI_SYS_VENDOR).unwrap_or_default(),
+        &read_trimmed(DMI_PRODUCT_NAME).unwrap_or_default(),
+    );
+    let vm = |hypervisor: &String| match hypervisor.as_str() {
+        UNKNOWN_HYPERVISOR => "a VM (unknown hypervisor)".to_string(),
+        hypervisor => format!("a {} VM", hypervisor),
+    };
+    match (container, hypervisor) {
+        (Some(runtime), hypervisor) => Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "in a {} container{}: changes affect the whole {}, not just the container",
+                runtime,
+                hypervisor.as_ref().map_or(String::new(), |hypervisor| format!(" in {}", vm(hypervisor))),
+                if hypervisor.is_some() { "VM" } else { "host" }
+            ),
+        ),
+        (None, Some(hypervisor)) => Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "in {}: changes affect the virtual CPUs of the VM, the host may still use the physical cores",
+                vm(&hypervisor)
+            ),
+        ),
+        (None, None) => Check::new(name, Status::Pass, "bare metal"),
+    }
+}
+
+/// Whether SMT siblings can be brought online and SMT can be controlled at runtime.
+fn check_smt() -> Check {
+    let name = "SMT control";
+    match cpu::boot_params::smt_control().as_deref() {
+        Some("on") => Check::new(name, Status::Pass, "SMT is enabled"),
+        Some("notsupported") => Check::new(name, Status::Pass, "the CPU does not support SMT"),
+        Some("off") => Check::new(
+            name,
+            Status::Warn,
+            format!("SMT is disabled, SMT siblings can't be brought online until `echo on > {}`", LINUX_SMT_CONTROL),
+        ),
+        Some("forceoff") => Check::new(name, Status::Warn, "SMT is disabled until reboot (nosmt=force), SMT siblings can't be brought online"),
+        Some("notimplemented") => Check::new(name, Status::Warn, "the kernel does not implement SMT control on this architecture"),
+        Some(control) => Check::new(name, Status::Warn, format!("unknown SMT control '{}'", control)),
+        None => Check::new(name, Status::Warn, format!("{} is missing, the kernel may be older than 4.19", LINUX_SMT_CONTROL)),
+    }
+}
+
+/// Which privileges `cores` runs with, see `sys_linux::drop_privileges()`.
+fn check_privileges(drop_result: &DropPrivilegeResult) -> Check {
+    let name = "privileges";
+    match drop_result {
+        DropPrivilegeResult::Dropped => {
+            Check::new(name, Status::Pass, "run with sudo, root privileges are used only for writing to sysfs")
+        }
+        DropPrivilegeResult::PureRoot => Check::new(name, Status::Pass, "run as root"),
+        DropPrivilegeResult::NotRoot => Check::new(
+            name,
+            Status::Warn,
+            "not root: the state can be read but not changed, try `sudo cores` (`cores run --soft` needs no root)",
+        ),
+    }
+}
+
+/// Whether `cores` itself or any cgroup is restricted to a subset of the online cores.
+fn check_cgroups(state: &CPUState) -> Vec<Check> {
+    let online: HashSet<usize> = state.online_cores().into_iter().collect();
+    let mut checks = Vec::new();
+
+    let name = "cgroup restrictions";
+    let affinity = sys_linux::cpu_affinity();
+    let confined = cpu::cpuset::audit();
+    checks.push(match (&affinity, &confined) {
+        (Ok(affinity), _) if !online.is_subset(affinity) => Check::new(
+            name,
+            Status::Warn,
+            format!(
+                "`cores` itself may only run on CPU(s) {}, its cgroup or CPU affinity is restricted",
+                core_list::format(affinity.intersection(&online).copied())
+            ),
+        ),
+        (_, Ok(confined)) if !confined.is_empty() => Check::new(
+            name,
+            Status::Warn,
+            format!("{} cgroup(s) can't use all the online cores, see `cores cpuset audit`", confined.len()),
+        ),
+        (Err(e), _) => Check::new(name, Status::Warn, format!("Could not read the CPU affinity. {}", e)),
+        (_, Err(e)) => Check::new(name, Status::Warn, format!("Could not audit the cpusets. {}", e)),
+        _ => Check::new(name, Status::Pass, "all the online cores are available to all the cgroups"),
+    });
+
+    let name = "cpuset partitions";
+    checks.push(match cpu::cpuset::partition::partition_cgroup() {
+        Ok(_) => Check::new(name, Status::Pass, "`cores isolate` is supported (cgroup v2 cpuset controller)"),
+        Err(e) => Check::new(name, Status::Warn, format!("`cores isolate` is not supported. {}", e)),
+    });
+    checks
+}
+
+/// Whether kernel command line parameters affect CPU hotplug or isolation.
+fn check_boot_params() -> Check {
+    let name = "boot parameters";
+    let notes = cpu::boot_params::get().notes();
+    if notes.is_empty() {
+        Check::new(name, Status::Pass, "no kernel parameters affect CPU hotplug")
+    } else {
+        Check::new(name, Status::Warn, notes.join("; "))
+    }
+}
+
+/// Checks whether and how `cores` can work in the current environment: kernel support, sysfs,
+/// containers and VMs, SMT, privileges, cgroups and boot parameters. Needs no root privileges.
+/// # Arguments
+/// * `drop_result` - The result of `sys_linux::drop_privileges()` at startup.
+pub fn run(drop_result: &DropPrivilegeResult) -> Vec<Check> {
+    let state = match cpu::get_state() {
+        Ok(state) => state,
+        Err(e) => return vec![Check::new("kernel hotplug", Status::Fail, e.to_string())],
+    };
+    let hotpluggable = cpu::get_hotpluggable_cores().unwrap_or_default();
+
+    let mut checks = vec![
+        check_kernel_hotplug(&state),
+        check_hotpluggable(&state, &hotpluggable),
+        check_sysfs(&hotpluggable),
+        check_virtualization(),
+        check_smt(),
+        check_privileges(drop_result),
+    ];
+    checks.extend(check_cgroups(&state));
+    checks.push(check_boot_params());
+    checks
+}
+
+fn count(checks: &[Check], status: Status) -> usize {
+    checks.iter().filter(|check| check.status == status).count()
+}
+
+#[derive(Serialize)]
+struct Rendered<'a> {
+    checks: &'a [Check],
+    passed: usize,
+    warnings: usize,
+    failed: usize,
+}
+
+fn render_as_text(checks: &[Check]) {
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    println!("CORES DOCTOR");
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    for check in checks {
+        let status = match check.status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        println!("[{}] {:<20} {}", status, check.name, check.detail);
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――");
+    println!(
+        "{} passed, {} warning(s), {} failed",
+        count(checks, Status::Pass),
+        count(checks, Status::Warn),
+        count(checks, Status::Fail)
+    );
+}
+
+/// Renders the result of `run()` in the desired format.
+/// # Returns
+/// Whether all the checks passed or only warned, i.e. none failed.
+pub fn render(checks: &[Check], as_json: bool) -> bool {
+    if as_json {
+        let rendered = Rendered {
+            checks,
+            passed: count(checks, Status::Pass),
+            warnings: count(checks, Status::Warn),
+            failed: count(checks, Status::Fail),
+        };
+        println!("{}", serde_json::to_string(&rendered).unwrap_or_default());
+    } else {
+        render_as_text(checks);
+    }
+    count(checks, Status::Fail) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_read_only() {
+        let mounts = "\
+sysfs /sys sysfs ro,nosuid,nodev,noexec,relatime 0 0
+proc /proc proc rw,relatime 0 0
+sysfs /sys sysfs rw,relatime 0 0
+";
+        assert_eq!(is_read_only(mounts, "/sys"), Some(false));
+        assert_eq!(is_read_only(&mounts.lines().take(2).collect::<Vec<_>>().join("\n"), "/sys"), Some(true));
+        assert_eq!(is_read_only(mounts, "/dev"), None);
+    }
+
+    #[test]
+    fn test_detect_container() {
+        assert_eq!(detect_container(true, false, ""), Some("Docker"));
+        assert_eq!(detect_container(false, false, "0::/kubepods/besteffort/pod1"), Some("Kubernetes"));
+        assert_eq!(detect_container(false, false, "0::/init.scope"), None);
+    }
+
+    #[test]
+    fn test_detect_hypervisor() {
+        let cpuinfo = "processor\t: 0\nflags\t\t: fpu vme hypervisor lahf_lm\n";
+        assert_eq!(detect_hypervisor(cpuinfo, None, "QEMU", "Standard PC"), Some("QEMU/KVM".to_string()));
+        assert_eq!(
+            detect_hypervisor("", None, "Microsoft Corporation", "Virtual Machine"),
+            Some("Hyper-V".to_string())
+        );
+        assert_eq!(detect_hypervisor(cpuinfo, None, "", ""), Some(UNKNOWN_HYPERVISOR.to_string()));
+        assert_eq!(detect_hypervisor("flags\t\t: fpu vme\n", None, "Dell Inc.", "PowerEdge"), None);
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+/// Parses a human-friendly duration.
+/// A duration is one or more numbers, each followed by a unit: `ms`, `s`, `m` or `h`.
+/// A number without a unit is interpreted as seconds.
+///
+/// **Valid input examples:** `30`, `30s`, `500ms`, `5m`, `1h`, `1m30s`
+pub fn parse(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Duration must not be empty".to_string());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        if digits_end == 0 {
+            return Err(format!("Invalid duration '{}'. Expected e.g. 30s, 5m, 1h or 500ms", input));
+        }
+        let value: u64 = rest[..digits_end]
+            .parse()
+            .map_err(|_| format!("Invalid duration '{}'. The number is too large", input))?;
+        rest = &rest[digits_end..];
+
+        let unit_end = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let part = match &rest[..unit_end] {
+            "ms" => Duration::from_millis(value),
+            "" | "s" => Duration::from_secs(value),
+            "m" => Duration::from_secs(value * 60),
+            "h" => Duration::from_secs(value * 3_600),
+            unit => {
+                return Err(format!("Invalid duration unit '{}' in '{}'. Use ms, s, m or h", unit, input));
+            }
+        };
+        total += part;
+        rest = &rest[unit_end..];
+    }
+    Ok(total)
+}
+
+/// Formats a duration the way [`parse`] accepts it, e.g. `1m30s` or `500ms`.
+pub fn format(duration: Duration) -> String {
+    if duration.subsec_millis() != 0 || duration.is_zero() {
+        return format!("{}ms", duration.as_millis());
+    }
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3_600, secs % 3_600 / 60, secs % 60);
+    let mut result = String::new();
+    if hours > 0 {
+        result.push_str(&format!("{}h", hours));
+    }
+    if minutes > 0 {
+        result.push_str(&format!("{}m", minutes));
+    }
+    if seconds > 0 {
+        result.push_str(&format!("{}s", seconds));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_seconds() {
+        assert_eq!(parse("30"), Ok(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_units() {
+        assert_eq!(parse("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse("2h"), Ok(Duration::from_secs(7_200)));
+    }
+
+    #[test]
+    fn test_compound() {
+        assert_eq!(parse("1m30s"), Ok(Duration::from_secs(90)));
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(parse("").is_err());
+        assert!(parse("s").is_err());
+        assert!(parse("10x").is_err());
+        assert!(parse("-5s").is_err());
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        for input in ["500ms", "45s", "10m", "2h", "1h5m3s"] {
+            assert_eq!(format(parse(input).unwrap()), input);
+        }
+    }
+}
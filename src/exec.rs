@@ -0,0 +1,140 @@
+pub mod soft;
+
+use crate::change;
+use crate::core_list;
+use crate::cpu;
+use crate::journal;
+use crate::sys_linux::{self, catch_interrupts, drop_privileges, interrupt_signal, restore_privileges, SIGINT};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check for interrupts while waiting for a command to exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Creates a `Command` from a program followed by its arguments.
+pub fn command_from(command: &[String]) -> Command {
+    let mut child = Command::new(&command[0]);
+    child.args(&command[1..]);
+    child
+}
+
+/// Starts `child` as the user who ran `cores`, i.e. as the original user in case of `sudo`.
+/// The command runs as root only if `cores` was run by root itself.
+/// # Arguments
+/// * `child` - The command to run, see `command_from()`.
+/// * `cpu_mask` - Restricts the command to these CPUs, if given. See `sys_linux::cpu_mask()`.
+pub fn spawn_as_invoking_user(mut child: Command, cpu_mask: Option<Vec<u64>>) -> Result<Child, Box<dyn Error>> {
+    if let Some(mask) = cpu_mask {
+        // Safety: `set_cpu_affinity()` only makes an async-signal-safe system call.
+        unsafe { child.pre_exec(move || sys_linux::set_cpu_affinity(&mask)) };
+    }
+
+    let program = child.get_program().to_string_lossy().to_string();
+    let sudoer = sys_linux::sudoer().map_err(|e| format!("Could not run `{}` as the original user. {}", program, e))?;
+    let spawned = match sudoer {
+        Some(sudoer) => {
+            // Safety: `switch_to()` only makes async-signal-safe system calls.
+            unsafe { child.pre_exec(move || sys_linux::switch_to(&sudoer)) };
+            restore_privileges(); // switching the user in the child requires root access
+            let spawned = child.spawn();
+            drop_privileges(); // drop root access
+            spawned
+        }
+        None => child.spawn(),
+    };
+    spawned.map_err(|e| Box::from(format!("Could not run `{}`. {}", program, e)))
+}
+
+/// Waits for `child` to exit.
+/// SIGTERM and SIGHUP received by `cores` in the meantime are forwarded to the child, SIGINT (Ctrl-C)
+/// is not as the terminal delivers it to the child anyway. `cores` itself keeps running, so that
+/// it can clean up after the child. Requires `catch_interrupts()` to have been called.
+/// # Returns
+/// The exit status of the child and the moment it exited.
+pub fn wait(mut child: Child) -> io::Result<(ExitStatus, Instant)> {
+    let pid = child.id();
+
+    // Wait in a separate thread, so that the exit is noticed immediately while polling for interrupts.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let result = child.wait();
+        let _ = sender.send(result.map(|status| (status, Instant::now())));
+    });
+
+    let mut forwarded_signal = None;
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(result) => return result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::other("Lost track of the child process"));
+            }
+        }
+
+        if let Some(signum) = interrupt_signal() {
+            if signum != SIGINT && forwarded_signal != Some(signum) {
+                if let Err(e) = sys_linux::send_signal(pid, signum) {
+                    eprintln!("Could not forward signal {} to process {}. {}", signum, pid, e);
+                }
+                forwarded_signal = Some(signum);
+            }
+        }
+    }
+}
+
+/// Converts an exit status to an exit code the way shells do: `128 + N` for a command killed by signal N.
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+/// Applies `core_states`, runs `command` as the user who ran `cores` and restores the previous state
+/// of the cores when the command exits, even if `cores` is interrupted meanwhile.
+/// # Arguments
+/// * `core_states` - The state of the cores to run the command with.
+/// * `action` - Description of the change for the journal, e.g. `exec set 4`.
+/// * `command` - The program to run followed by its arguments.
+/// # Returns
+/// The exit code of the command.
+pub fn run(core_states: &HashMap<usize, bool>, action: &str, command: &[String]) -> Result<i32, Box<dyn Error>> {
+    let state_before = cpu::get_state()?;
+    let since = journal::last_id();
+
+    // From now on, an interrupt must not terminate `cores` before the previous state is restored
+    catch_interrupts();
+
+    change::apply(action, None, || cpu::set_cores(core_states))?;
+
+    // Don't start the command if `cores` was interrupted during the change, restore right away
+    let interrupted_by = interrupt_signal();
+    let result = match interrupted_by {
+        Some(_) => None,
+        None => {
+            eprintln!(
+                "Running `{}` with cores {} online",
+                command.join(" "),
+                cpu::get_state()?.online_core_list()
+            );
+            Some(spawn_as_invoking_user(command_from(command), None).and_then(|child| Ok(wait(child)?)))
+        }
+    };
+
+    let cores_online: HashSet<usize> = state_before.online_cores().into_iter().collect();
+    let reason = format!("revert to {} (exec finished)", core_list::format(cores_online.iter().copied()));
+    change::restore(&cores_online, &reason, since)?;
+    eprintln!("Restored the previous state, cores {} online", cpu::get_state()?.online_core_list());
+
+    let Some(result) = result else {
+        eprintln!("Interrupted before `{}` was started", command.join(" "));
+        return Ok(128 + interrupted_by.unwrap_or(0));
+    };
+    let (status, _) = result?;
+    Ok(exit_code(&status))
+}
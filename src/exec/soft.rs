@@ -0,0 +1,140 @@
+use crate::core_list;
+use crate::exec::{command_from, exit_code, spawn_as_invoking_user, wait};
+use crate::sys_linux::{self, catch_interrupts};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const CGROUP_V2_ROOT: &str = "/sys/fs/cgroup";
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// A cgroup v2 cpuset created for a single command.
+struct CpusetCgroup {
+    path: PathBuf,
+}
+
+impl CpusetCgroup {
+    /// Creates a child cgroup limited to `cores` in the closest ancestor of the current cgroup
+    /// that has the cpuset controller enabled and is writable by the current user (i.e. delegated).
+    /// Returns `None` if there is no such cgroup, e.g. on cgroup v1 or without delegation.
+    fn create(cores: &HashSet<usize>) -> Option<CpusetCgroup> {
+        let content = fs::read_to_string(PROC_SELF_CGROUP).ok()?;
+        // cgroup v2 is the single hierarchy with ID 0, e.g. `0::/user.slice/user-1000.slice/session-2.scope`
+        let own_cgroup = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+        let mut parent = Path::new(CGROUP_V2_ROOT).join(own_cgroup.trim_start_matches('/'));
+        let cpu_list = core_list::format(cores.iter().copied());
+
+        loop {
+            let subtree_control = fs::read_to_string(parent.join("cgroup.subtree_control")).unwrap_or_default();
+            if subtree_control.split_whitespace().any(|controller| controller == "cpuset") {
+                let path = parent.join(format!("cores-{}", process::id()));
+                if fs::create_dir(&path).is_ok() {
+                    if fs::write(path.join("cpuset.cpus"), &cpu_list).is_ok() {
+                        return Some(CpusetCgroup { path });
+                    }
+                    let _ = fs::remove_dir(&path);
+                }
+            }
+            if parent == Path::new(CGROUP_V2_ROOT) || !parent.pop() {
+                return None;
+            }
+        }
+    }
+
+    /// Moves the process `pid` into the cgroup.
+    fn add(&self, pid: u32) -> std::io::Result<()> {
+        fs::write(self.path.join("cgroup.procs"), pid.to_string())
+    }
+
+    /// Removes the cgroup. Only possible once all the processes in it have exited.
+    fn remove(&self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            eprintln!("Could not remove cgroup {}. {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Selects the CPUs to run the command on, among the CPUs `cores` itself is allowed to run on.
+/// # Arguments
+/// * `set` - Select this number of CPUs, starting from the lowest numbered one.
+/// * `custom` - Select these CPUs.
+fn select_cores(set: Option<usize>, custom: Option<&HashSet<usize>>) -> Result<HashSet<usize>, Box<dyn Error>> {
+    let allowed = sys_linux::cpu_affinity()?;
+
+    if let Some(selected) = custom {
+        let unavailable: Vec<usize> = selected.difference(&allowed).copied().collect();
+        if !unavailable.is_empty() {
+            return Err(Box::from(format!(
+                "CPU(s) {} are offline or not available to `cores` (available: {}). Soft mode can't bring CPUs online",
+                core_list::format(unavailable),
+                core_list::format(allowed)
+            )));
+        }
+        return Ok(selected.clone());
+    }
+
+    let mut allowed: Vec<usize> = allowed.into_iter().collect();
+    allowed.sort_unstable();
+    allowed.truncate(set.unwrap_or(allowed.len()).max(1));
+    Ok(allowed.into_iter().collect())
+}
+
+/// Runs `command` restricted to the selected CPUs without changing the state of the cores, hence
+/// without root privileges. The command is started with a CPU affinity mask covering only the
+/// selected CPUs and, if a delegated cgroup v2 with the cpuset controller is available, in a child
+/// cgroup limited to them, so that tools like `nproc` and thread pools see the reduced CPU count.
+/// # Arguments
+/// * `set` - Run the command on this number of CPUs, starting from the lowest numbered one.
+/// * `custom` - Run the command on these CPUs.
+/// * `command` - The program to run followed by its arguments.
+/// # Returns
+/// The exit code of the command.
+pub fn run(set: Option<usize>, custom: Option<&HashSet<usize>>, command: &[String]) -> Result<i32, Box<dyn Error>> {
+    let cores = select_cores(set, custom)?;
+    let cpu_list = core_list::format(cores.iter().copied());
+    let cgroup = CpusetCgroup::create(&cores);
+
+    match &cgroup {
+        Some(cgroup) => eprintln!(
+            "Running `{}` on CPU(s) {} (CPU affinity and cgroup cpuset {})",
+            command.join(" "),
+            cpu_list,
+            cgroup.path.display()
+        ),
+        None => eprintln!(
+            "Running `{}` on CPU(s) {} (CPU affinity only, no delegated cgroup v2 cpuset available)",
+            command.join(" "),
+            cpu_list
+        ),
+    }
+
+    // `cores` has to outlive the command to remove the cgroup
+    catch_interrupts();
+
+    let child = match spawn_as_invoking_user(command_from(command), Some(sys_linux::cpu_mask(&cores))) {
+        Ok(child) => child,
+        Err(e) => {
+            if let Some(cgroup) = &cgroup {
+                cgroup.remove();
+            }
+            return Err(e);
+        }
+    };
+
+    // The CPU affinity is in place before the command starts, the cgroup follows right after.
+    if let Some(cgroup) = &cgroup {
+        if let Err(e) = cgroup.add(child.id()) {
+            eprintln!("Could not move the command to {}, relying on CPU affinity. {}", cgroup.path.display(), e);
+        }
+    }
+
+    let result = wait(child);
+    if let Some(cgroup) = &cgroup {
+        cgroup.remove();
+    }
+
+    let (status, _) = result?;
+    Ok(exit_code(&status))
+}
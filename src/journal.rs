@@ -0,0 +1,298 @@
+use crate::change::settings::Settings;
+use crate::cpu::cpu_state::CPUState;
+use crate::json;
+use crate::sys_linux::{invoking_user, lock_exclusive};
+use crate::timestamp;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+
+pub const JOURNAL_DIR: &str = "/var/lib/cores";
+const JOURNAL_FILE: &str = "/var/lib/cores/journal";
+
+/// The settings changed by an operation other than taking cores online or offline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettingsChange {
+    pub before: Settings,
+    pub after: Settings,
+}
+
+/// A state-changing operation recorded in the journal.
+///
+/// On disk every entry is a single tab-separated line:
+/// `<id>\t<timestamp>\t<user>\t<undo_of>\t<action>\t<online before>\t<online after>\t<settings>`
+/// where `<settings>` is `-` for hotplug operations (and missing in entries of older versions).
+#[derive(Debug, PartialEq)]
+pub struct JournalEntry {
+    /// Sequential number of the entry, starting from 1.
+    pub id: usize,
+    /// Time of the operation, seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The user who ran `cores` (the sudoer in case of `sudo`).
+    pub user: String,
+    /// The ID of the entry reverted by this one, if this entry is an undo or a revert. The later
+    /// entries are reverted as well, e.g. the steps of `cores sweep` by the revert at its end.
+    pub undo_of: Option<usize>,
+    /// Human-readable description of the operation, e.g. `set 2`.
+    pub action: String,
+    /// The cores that were online before the operation, in CPU list format.
+    pub online_before: String,
+    /// The cores that were online after the operation, in CPU list format.
+    pub online_after: String,
+    /// The settings before and after the operation, `None` if it took cores online or offline.
+    pub settings: Option<SettingsChange>,
+}
+
+impl JournalEntry {
+    fn to_line(&self) -> String {
+        let undo_of = self.undo_of.map_or("-".to_string(), |id| id.to_string());
+        let settings = match &self.settings {
+            Some(settings) => serde_json::to_string(settings).unwrap_or_default(),
+            None => "-".to_string(),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id, self.timestamp, self.user, undo_of, self.action, self.online_before, self.online_after, settings
+        )
+    }
+
+    fn from_line(line: &str) -> Option<JournalEntry> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 && fields.len() != 8 {
+            return None;
+        }
+        Some(JournalEntry {
+            id: fields[0].parse().ok()?,
+            timestamp: fields[1].parse().ok()?,
+            user: fields[2].to_string(),
+            undo_of: fields[3].parse().ok(),
+            action: fields[4].to_string(),
+            online_before: fields[5].to_string(),
+            online_after: fields[6].to_string(),
+            settings: fields.get(7).and_then(|settings| serde_json::from_str(settings).ok()),
+        })
+    }
+}
+
+/// Reads all the entries of the journal. A missing journal is treated as an empty one.
+pub fn read() -> Result<Vec<JournalEntry>, Box<dyn Error>> {
+    let content = match fs::read_to_string(JOURNAL_FILE) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Box::from(format!("Could not read {JOURNAL_FILE}. {e}"))),
+    };
+
+    Ok(content.lines().filter_map(JournalEntry::from_line).collect())
+}
+
+/// Returns the ID of the last entry of the journal, 0 if there are none or the journal can't be
+/// read. The next operation is recorded with a greater ID, see `change::restore()`.
+pub fn last_id() -> usize {
+    read().ok().and_then(|entries| entries.last().map(|e| e.id)).unwrap_or(0)
+}
+
+/// Returns the most recent operation that has not been undone yet.
+/// Undo entries cancel out the operations they revert, which makes `cores undo` repeatable.
+pub fn last_undoable(entries: &[JournalEntry]) -> Option<&JournalEntry> {
+    let mut stack: Vec<&JournalEntry> = Vec::new();
+    for entry in entries {
+        match entry.undo_of {
+            Some(undone_id) => stack.retain(|e| e.id < undone_id),
+            None => stack.push(entry),
+        }
+    }
+    stack.pop()
+}
+
+/// Appends an operation that took cores online or offline to the journal. Requires root privileges.
+/// Nothing is recorded if the operation did not change the state of the cores, except for undo
+/// entries which always have to be recorded to mark the reverted operation as undone.
+/// Failing to write the journal is reported but does not fail the operation itself.
+pub fn record(action: &str, undo_of: Option<usize>, before: &CPUState, after: &CPUState) {
+    let online_before = before.online_core_list();
+    let online_after = after.online_core_list();
+    if undo_of.is_none() && online_before == online_after {
+        return;
+    }
+
+    let entry = |id| JournalEntry {
+        id,
+        timestamp: timestamp::now(),
+        user: invoking_user(),
+        undo_of,
+        action: action.to_string(),
+        online_before,
+        online_after,
+        settings: None,
+    };
+
+    if let Err(e) = append(entry) {
+        eprintln!("Could not record the operation in {JOURNAL_FILE}. {e}");
+    }
+}
+
+/// Appends an operation that changed other settings of the cores to the journal, see `Settings`.
+/// Requires root privileges. Like with `record()`, nothing is recorded if the settings did not
+/// change, except for undo entries.
+/// # Arguments
+/// * `state` - The state of the cores, which the operation did not change.
+pub fn record_settings(action: &str, undo_of: Option<usize>, state: &CPUState, before: Settings, after: Settings) {
+    if undo_of.is_none() && before == after {
+        return;
+    }
+
+    let online = state.online_core_list();
+    let entry = |id| JournalEntry {
+        id,
+        timestamp: timestamp::now(),
+        user: invoking_user(),
+        undo_of,
+        action: action.to_string(),
+        online_before: online.clone(),
+        online_after: online,
+        settings: Some(SettingsChange { before, after }),
+    };
+
+    if let Err(e) = append(entry) {
+        eprintln!("Could not record the operation in {JOURNAL_FILE}. {e}");
+    }
+}
+
+/// Appends the entry built for the next ID. The journal is locked from reading the last ID until
+/// the entry is written, so that concurrent runs of `cores` (e.g. the background process of
+/// `--for --detach`) don't record the same ID twice.
+fn append<F>(entry: F) -> std::io::Result<()>
+where
+    F: FnOnce(usize) -> JournalEntry,
+{
+    fs::create_dir_all(JOURNAL_DIR)?;
+    let mut file = fs::OpenOptions::new().create(true).read(true).append(true).open(JOURNAL_FILE)?;
+    lock_exclusive(&file)?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let id = content.lines().rev().find_map(JournalEntry::from_line).map_or(1, |e| e.id + 1);
+    writeln!(file, "{}", entry(id).to_line())
+}
+
+fn render_as_text(entries: &[JournalEntry]) {
+    if entries.is_empty() {
+        println!("No operations recorded in {JOURNAL_FILE}");
+        return;
+    }
+    println!(
+        "{:>4}  {:<20}  {:<12}  {:<24}  {:<14}  ONLINE AFTER",
+        "#", "TIME (UTC)", "USER", "ACTION", "ONLINE BEFORE"
+    );
+    for entry in entries {
+        println!(
+            "{:>4}  {:<20}  {:<12}  {:<24}  {:<14}  {}",
+            entry.id,
+            timestamp::format(entry.timestamp),
+            entry.user,
+            entry.action,
+            entry.online_before,
+            entry.online_after
+        );
+    }
+}
+
+fn render_as_json(entries: &[JournalEntry]) {
+    let entries = entries
+        .iter()
+        .map(|e| {
+            format!(
+                r#"{{"id":{},"timestamp":"{}","user":{},"undo_of":{},"action":{},"online_before":"{}","online_after":"{}","settings":{}}}"#,
+                e.id,
+                timestamp::format(e.timestamp),
+                json::string(&e.user),
+                e.undo_of.map_or("null".to_string(), |id| id.to_string()),
+                json::string(&e.action),
+                e.online_before,
+                e.online_after,
+                serde_json::to_string(&e.settings).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(",");
+    println!("[{}]", entries);
+}
+
+/// Renders the journal in the desired format.
+/// # Arguments
+/// * `entries` - The journal entries, oldest first.
+/// * `as_json` - Whether to render the entries in JSON format or in human-readable text format.
+pub fn render(entries: &[JournalEntry], as_json: bool) {
+    if as_json {
+        render_as_json(entries);
+    } else {
+        render_as_text(entries);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: usize, undo_of: Option<usize>) -> JournalEntry {
+        JournalEntry {
+            id,
+            timestamp: 1_728_137_007,
+            user: "alice".to_string(),
+            undo_of,
+            action: "set 2".to_string(),
+            online_before: "0-5".to_string(),
+            online_after: "0-1".to_string(),
+            settings: None,
+        }
+    }
+
+    #[test]
+    fn test_line_round_trip() {
+        let original = entry(3, Some(2));
+        assert_eq!(JournalEntry::from_line(&original.to_line()), Some(original));
+    }
+
+    #[test]
+    fn test_settings_line_round_trip() {
+        let mut original = entry(5, None);
+        original.settings = Some(SettingsChange {
+            before: Settings::Pstate { mode: Some("active".to_string()) },
+            after: Settings::Pstate { mode: Some("passive".to_string()) },
+        });
+        assert_eq!(JournalEntry::from_line(&original.to_line()), Some(original));
+
+        // Entries of older versions have no settings
+        let line = "1\t1728137007\talice\t-\tset 2\t0-5\t0-1";
+        assert_eq!(JournalEntry::from_line(line), Some(entry(1, None)));
+    }
+
+    #[test]
+    fn test_malformed_line() {
+        assert_eq!(JournalEntry::from_line("garbage"), None);
+    }
+
+    #[test]
+    fn test_last_undoable_is_repeatable() {
+        let mut entries = vec![entry(1, None), entry(2, None)];
+        assert_eq!(last_undoable(&entries).map(|e| e.id), Some(2));
+
+        entries.push(entry(3, Some(2)));
+        assert_eq!(last_undoable(&entries).map(|e| e.id), Some(1));
+
+        entries.push(entry(4, Some(1)));
+        assert_eq!(last_undoable(&entries), None);
+    }
+
+    #[test]
+    fn test_last_undoable_after_revert() {
+        // `cores sweep` records its steps #2 and #3 and reverts them at the end with #4
+        let mut entries = vec![entry(1, None), entry(2, None), entry(3, None), entry(4, Some(2))];
+        assert_eq!(last_undoable(&entries).map(|e| e.id), Some(1));
+
+        // `cores chaos` stopped before its first event, its revert #5 cancels nothing
+        entries.push(entry(5, Some(5)));
+        assert_eq!(last_undoable(&entries).map(|e| e.id), Some(1));
+    }
+}
@@ -1,21 +1,59 @@
+mod change;
+mod chaos;
 mod core_list;
 mod cpu;
+mod doctor;
+mod duration;
+mod exec;
+mod journal;
+mod json;
+mod monitor;
+mod scenario;
+mod sweep;
 mod sys_linux;
+mod timestamp;
+mod tui;
+mod watch;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
 
+use crate::change::settings::Settings;
 use crate::cpu::cpu_state::CPUState;
-use crate::sys_linux::{drop_privileges, restore_privileges, DropPrivilegeResult};
+use crate::cpu::pinned::PinnedTask;
+use crate::sys_linux::{catch_interrupts, drop_privileges, DropPrivilegeResult};
 
 /// CLI argument parser using `clap`.
 #[derive(Parser, Debug)]
 #[command(
     name = "cores",
-    override_usage = " cores <CORE_NUMBER> <on|off> [--json]\n\
-                      \tcores --set <NUMBER> | --reset | --custom <RANGE> [--json]",
+    override_usage = " cores <CORE_NUMBER> <on|off> [--force] [--json]\n\
+                      \tcores --set <NUMBER> | --reset | --custom <RANGE> [--force] [--json | --format <FORMAT>]\n\
+                      \tcores <CORE_NUMBER> <on|off> | --set <NUMBER> | --custom <RANGE> --for <DURATION> [--detach]\n\
+                      \tcores <CORE_NUMBER> <on|off> | --set <NUMBER> | --custom <RANGE> --confirm-within <DURATION>\n\
+                      \tcores [--only <online|offline>] [--layout <auto|list|grid>] [--json | --format <FORMAT>]\n\
+                      \tcores --watch [--interval <DURATION>] [--only <online|offline>] [--layout <auto|list|grid>] [--json | --format <FORMAT>]\n\
+                      \tcores list [-o <COLUMNS>] [--no-headings] [--raw] [--json]\n\
+                      \tcores monitor [--poll [<INTERVAL>]] [--json]\n\
+                      \tcores tui [--force]\n\
+                      \tcores undo | history [--json]\n\
+                      \tcores exec | run [--soft] --set <NUMBER> | --custom <RANGE> -- <COMMAND>...\n\
+                      \tcores sweep [--counts <COUNTS>] [--repeat <N>] [--csv | --json] -- <COMMAND>...\n\
+                      \tcores chaos [--duration <DURATION>] [--min-online <N>] [--interval <MIN..MAX>] [--seed <SEED>] [-- <COMMAND>...]\n\
+                      \tcores scenario run <PLAN> | replay <LOG> [--log <FILE>] [-- <COMMAND>...]\n\
+                      \tcores hotplug states | fail <CORE_NUMBER> <STATE> | target <CORE_NUMBER> <STATE>\n\
+                      \tcores cpuset audit | doctor [--json]\n\
+                      \tcores isolate <CPU_LIST> | unisolate [CPU_LIST] [--json]\n\
+                      \tcores freq [-c <CPU_LIST>] [--min <FREQUENCY>] [--max <FREQUENCY>] [--governor <GOVERNOR>] [--boost <on|off>] [--json]\n\
+                      \tcores epp [-c <CPU_LIST>] [VALUE] | pstate status | set <MODE> [--json]\n\
+                      \tcores idle [-c <CPU_LIST>] [--disable <STATES> | --enable <STATES> | --restore] [--json]",
+    args_conflicts_with_subcommands = true,
     about = "\
     cores ― a convenience tool for enabling and disabling CPU cores on Linux systems.\n\
     - Run without parameters to see the current state of the CPU cores.\n\
@@ -34,6 +72,56 @@ use crate::sys_linux::{drop_privileges, restore_privileges, DropPrivilegeResult}
    cores -c 1-3,5        Set cores 0, 1, 2, 3, 5 online, set all the other cores offline.
    cores -c 0-2,4-5      Set cores 0, 1, 2, 4, 5 online, set all the other cores offline.
    cores -c \"0-2, 4-5\"   Set cores 0, 1, 2, 4, 5 online, set all the other cores offline.
+   cores -s 2 --for 5m   Set cores 0 and 1 online and restore the previous state after 5 minutes.
+   cores 3 off --for 1h --detach
+                         Set core 3 offline and restore it in the background after an hour.
+   cores -c 0-1 --confirm-within 30s
+                         Set cores 0 and 1 online and restore the previous state unless confirmed.
+   cores --only offline  List only the offline cores, e.g. on machines with hundreds of cores.
+   cores --layout grid   List the cores in a compact grid of 16 cores per row.
+   cores --format yaml   Print the state in YAML, following the schema in schema/cores.schema.json.
+   taskset -c $(cores --format list) ./bench
+                         Run `./bench` on the online cores.
+   eval \"$(cores --format env)\"
+                         Set CORES_TOTAL, CORES_ONLINE, CORES_OFFLINE and CORES_ISOLATED in a shell.
+   cores --watch         Redraw the state every second, highlight the cores that changed.
+   cores list -o CPU,ONLINE,SOCKET,CORE,MHZ
+                         Print a table of the CPUs with the given columns, like `lscpu -e`.
+   cores monitor --json  Print CPU hotplug events as they happen, one JSON line per event.
+   sudo cores tui        Toggle cores in an interactive grid and apply the plan with `a`.
+   cores exec -s 4 -- ./bench --threads 8
+                         Run `./bench` with cores 0-3 online and restore the previous state afterwards.
+   cores run --soft -s 4 -- nproc
+                         Run `nproc` restricted to 4 CPUs, without root privileges. Prints 4.
+   cores sweep --counts 1,2,4,8 --repeat 5 -- ./bench
+                         Run `./bench` 5 times with 1, 2, 4 and 8 cores online, report speedup.
+   cores chaos --duration 10m --min-online 2 --seed 42 -- ./server
+                         Randomly take cores offline and online while `./server` runs.
+   cores scenario run plan.toml --log events.ndjson -- ./server
+                         Execute the timeline in plan.toml while `./server` runs, log the events.
+   cores scenario replay events.ndjson
+                         Execute the same changes at the same times again.
+   cores hotplug fail 3 sched:active
+                         Make the next hotplug operation of core 3 fail at state `sched:active`.
+   cores hotplug target 3 120
+                         Park core 3 at the intermediate hotplug state 120.
+   cores isolate 2-3     Remove cores 2 and 3 from general scheduling without taking them offline.
+   cores unisolate       Return all the isolated cores to general scheduling.
+   cores freq -c 0-3 --max 1.8GHz --governor powersave
+                         Cap cores 0-3 at 1.8 GHz and switch them to the powersave governor.
+   cores freq --boost off
+                         Disable boost (turbo) for all the cores.
+   cores epp -c 2-3 power
+                         Set the energy performance preference of cores 2 and 3 to `power`.
+   cores pstate set passive
+                         Switch intel_pstate or amd-pstate to passive mode.
+   cores idle -c 2-3 --disable C6
+                         Keep cores 2 and 3 out of the C6 idle state, e.g. for latency tests.
+   cores idle --restore  Restore the idle states changed with `cores idle`.
+   cores cpuset audit    List the cgroups that can't use all the online cores.
+   cores doctor          Check whether and how the cores can be changed in this environment.
+   cores undo            Revert the last change, run again to revert the one before it.
+   cores history         List the changes recorded in /var/lib/cores/journal.
 
 \x1b[4mAuthor\x1b[0m: Leo Boguslavskiy
 
@@ -43,6 +131,9 @@ use crate::sys_linux::{drop_privileges, restore_privileges, DropPrivilegeResult}
 
 )]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Specifies a core to set online or offline.
     #[arg(required = false, conflicts_with_all = &["set", "reset", "custom"])]
     core: Option<usize>,
@@ -60,26 +151,430 @@ struct Args {
     reset: bool,
 
     /// Use CPU list format (as in /sys/devices/system/cpu/online) to specify cores. E.g.: 0 | 0-5 | 1-3,5 | 0-2,4-5
-    #[arg(short, long, exclusive = true, conflicts_with_all = &["set", "reset", "core", "state"], value_name = "CPU_LIST")]
+    #[arg(short, long, conflicts_with_all = &["set", "reset", "core", "state"], value_name = "CPU_LIST")]
     custom: Option<String>,
 
+    /// Restore the previous state after DURATION, e.g. 30s | 5m | 1h. Ctrl-C restores it right away.
+    #[arg(long = "for", conflicts_with_all = &["reset", "confirm_within"], value_name = "DURATION", value_parser = duration::parse)]
+    for_duration: Option<Duration>,
+
+    /// Used with --for: restore the previous state by a background process, so that `cores` exits immediately.
+    #[arg(long, requires = "for_duration")]
+    detach: bool,
+
+    /// Ask for confirmation and restore the previous state unless the change is confirmed within DURATION.
+    #[arg(long, conflicts_with = "reset", value_name = "DURATION", value_parser = duration::parse)]
+    confirm_within: Option<Duration>,
+
+    /// Redraw the state of the cores continuously, highlighting the cores that changed and showing
+    /// when each core changed. With --json or --format, print the state whenever it changes. Ctrl-C stops watching.
+    #[arg(long, conflicts_with_all = &["core", "state", "set", "reset", "custom", "for_duration", "confirm_within"])]
+    watch: bool,
+
+    /// Used with --watch: the time between refreshes, e.g. 500ms | 1s | 5s.
+    #[arg(long, requires = "watch", default_value = "1s", value_name = "DURATION", value_parser = duration::parse)]
+    interval: Duration,
+
+    /// Take cores offline even if processes are pinned to them (their CPU affinity shrinks or is broken).
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// The output format of the state: json, json-pretty, yaml and toml follow the versioned schema
+    /// in schema/cores.schema.json, legacy is the layout of --json. env prints CORES_TOTAL=8-style
+    /// lines for `eval`, list just the online cores in CPU list format.
+    #[arg(long, value_enum, conflicts_with = "json", value_name = "FORMAT")]
+    format: Option<cpu::Format>,
+
+    /// List only the online or the offline cores.
+    #[arg(long, value_enum, value_name = "STATE")]
+    only: Option<cpu::Only>,
+
+    /// How to list the cores: one line per core, or a compact grid of 16 cores per row. `auto` uses
+    /// the grid if the list doesn't fit on the terminal.
+    #[arg(long, value_enum, default_value = "auto")]
+    layout: cpu::Layout,
+
     /// Print version of `cores`.
     #[arg(short, long, exclusive = true)]
     version: bool,
 
     /// Print state of the cores in JSON format.
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     json: bool,
 }
 
-fn print_cores_updated(cores_updated: usize, is_json: bool) {
-    if is_json {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Revert the last change recorded in the journal. Can be repeated to go further back.
+    Undo,
+    /// List the changes recorded in the journal, including the user and the time of each change.
+    History,
+    /// Print a table of the CPUs with the given columns, like `lscpu -e`. With --json, one object per CPU.
+    #[command(after_help = cpu::list::COLUMNS_HELP)]
+    List {
+        /// The columns to print, comma-separated, see below.
+        #[arg(short = 'o', long = "output", value_name = "COLUMNS", value_delimiter = ',', default_value = cpu::list::DEFAULT_COLUMNS, value_parser = cpu::list::parse_column)]
+        columns: Vec<cpu::list::Column>,
+
+        /// Don't print the line with the column names.
+        #[arg(long)]
+        no_headings: bool,
+
+        /// Separate the columns by a single space instead of aligning them.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Print CPU hotplug events (online, offline, add, remove) as they happen, received from the kernel
+    /// as uevents. With --json, one JSON line per event. Ctrl-C stops monitoring.
+    Monitor {
+        /// Poll the online and present cores in sysfs every INTERVAL (default: 100ms) instead, e.g. in
+        /// containers, which don't receive uevents. Also used if the uevent socket can't be opened.
+        #[arg(long, value_name = "INTERVAL", num_args = 0..=1, default_missing_value = "100ms", value_parser = duration::parse)]
+        poll: Option<Duration>,
+    },
+    /// Show the cores in a full-screen grid grouped by package and physical core, with their state,
+    /// utilization and frequency. Toggle cores with the keyboard, preview the plan and apply it with
+    /// one key (applying needs root privileges).
+    Tui,
+    /// Run a command with the given cores online and restore the previous state when it exits.
+    /// The command runs as the original user in case of `sudo`, its exit code is passed through.
+    #[command(visible_alias = "run")]
+    Exec {
+        /// Don't change the state of the cores (no root privileges needed), restrict the command to
+        /// the given CPUs with CPU affinity and a cgroup v2 cpuset (if delegated) instead.
+        #[arg(long)]
+        soft: bool,
+
+        /// Set NUMBER of cores online and set all the other cores offline while the command runs.
+        #[arg(short, long, value_name = "NUMBER", required_unless_present = "custom", conflicts_with = "custom")]
+        set: Option<usize>,
+
+        /// Set the cores in CPU_LIST online and all the other cores offline while the command runs.
+        #[arg(short, long, value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        custom: Option<HashSet<usize>>,
+
+        /// The command to run, followed by its arguments.
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Measure how a command scales with the number of cores: run it with 1, 2, 4 ... cores online,
+    /// report wall time, speedup and parallel efficiency, and restore the previous state afterwards.
+    Sweep {
+        /// The numbers of cores to run the command with, e.g. 1,2,4,8. Default: powers of two up to all the cores.
+        #[arg(long, value_delimiter = ',', value_name = "COUNTS")]
+        counts: Vec<usize>,
+
+        /// How many times to run the command with each number of cores.
+        #[arg(long, default_value_t = 1, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        repeat: u32,
+
+        /// Print the results in CSV format.
+        #[arg(long, conflicts_with = "json")]
+        csv: bool,
+
+        /// The command to run, followed by its arguments. Its output goes to stderr.
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Simulate core failures: randomly take hotpluggable cores offline and bring them back online,
+    /// logging every event, and restore the starting state at the end (also on Ctrl-C).
+    Chaos {
+        /// Stop after DURATION, e.g. 10m. Default: until the command exits or Ctrl-C.
+        #[arg(long, value_name = "DURATION", value_parser = duration::parse)]
+        duration: Option<Duration>,
+
+        /// Never let the number of online cores drop below N.
+        #[arg(long, default_value_t = 1, value_name = "N")]
+        min_online: usize,
+
+        /// Time between events: a fixed duration (e.g. 10s) or a random one within a range (e.g. 5s..30s).
+        #[arg(long, default_value = "5s..30s", value_name = "MIN..MAX", value_parser = chaos::parse_interval)]
+        interval: (Duration, Duration),
+
+        /// Seed for the random events, the same seed reproduces the same events. Default: random.
+        #[arg(long, value_name = "SEED")]
+        seed: Option<u64>,
+
+        /// A command to run during the chaos, followed by its arguments. Its output goes to stderr.
+        #[arg(last = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Execute a timeline of changes from a plan file, e.g. "at 2s offline core 3, at 5s keep only 0-1".
+    Scenario {
+        #[command(subcommand)]
+        command: ScenarioCommand,
+    },
+    /// Inspect the kernel's CPU hotplug state machine, inject failures and park cores at intermediate states.
+    Hotplug {
+        #[command(subcommand)]
+        command: HotplugCommand,
+    },
+    /// Remove cores from general scheduling without taking them offline, like `isolcpus=` at runtime:
+    /// add them to an isolated cgroup v2 cpuset partition (`/sys/fs/cgroup/cores.isolated`).
+    Isolate {
+        /// The cores to isolate, in CPU list format, e.g. 2-3.
+        #[arg(value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        cores: HashSet<usize>,
+    },
+    /// Return cores isolated by `cores isolate` to general scheduling.
+    Unisolate {
+        /// The cores to return, in CPU list format. Default: all of them.
+        #[arg(value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        cores: Option<HashSet<usize>>,
+    },
+    /// Show or change the cpufreq settings of the cores: governor, frequency limits and boost (turbo).
+    /// Without options, the current settings are shown (no root privileges needed).
+    Freq {
+        /// The cores to change, in CPU list format, e.g. 0-3. Default: all the cores.
+        #[arg(short, long, value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        custom: Option<HashSet<usize>>,
+
+        /// The lowest frequency the governor may use, e.g. 800MHz | 1.2GHz | 1200000 (kHz).
+        #[arg(long, value_name = "FREQUENCY", value_parser = cpu::freq::parse_frequency)]
+        min: Option<u64>,
+
+        /// The highest frequency the governor may use, e.g. 1.8GHz.
+        #[arg(long, value_name = "FREQUENCY", value_parser = cpu::freq::parse_frequency)]
+        max: Option<u64>,
+
+        /// The scaling governor, e.g. performance | powersave | schedutil (see `scaling_available_governors`).
+        #[arg(long, value_name = "GOVERNOR")]
+        governor: Option<String>,
+
+        /// Enable or disable boost (turbo) for all the cores.
+        #[arg(long, value_name = "on|off", value_parser = ["on", "off"])]
+        boost: Option<String>,
+    },
+    /// Show or set the energy performance preference (EPP) of the cores, with intel_pstate or amd-pstate.
+    /// Without VALUE, the current and available preferences are shown (no root privileges needed).
+    Epp {
+        /// The cores to change, in CPU list format, e.g. 0-3. Default: all the cores.
+        #[arg(short, long, value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        custom: Option<HashSet<usize>>,
+
+        /// The preference, e.g. performance | balance_performance | balance_power | power (see `cores epp`).
+        value: Option<String>,
+    },
+    /// Show or switch the operation mode of the intel_pstate or amd-pstate driver.
+    Pstate {
+        #[command(subcommand)]
+        command: PstateCommand,
+    },
+    /// Show the idle states (C-states) of the cores, disable or enable them, and restore the previous settings.
+    /// Without options, the idle states are shown (no root privileges needed).
+    Idle {
+        /// The cores to change, in CPU list format, e.g. 2-3. Default: all the cores.
+        #[arg(short, long, value_name = "CPU_LIST", value_parser = core_list::try_parse)]
+        custom: Option<HashSet<usize>>,
+
+        /// Disable idle states, by name or as stateK, e.g. C6 | C1E,C6 | state3. The previous settings are saved.
+        #[arg(long, value_delimiter = ',', value_name = "STATES", conflicts_with_all = &["enable", "restore"])]
+        disable: Vec<String>,
+
+        /// Enable idle states, by name or as stateK. The previous settings are saved.
+        #[arg(long, value_delimiter = ',', value_name = "STATES", conflicts_with = "restore")]
+        enable: Vec<String>,
+
+        /// Restore the idle state settings saved before the first `--disable` or `--enable`.
+        #[arg(long, conflicts_with = "custom")]
+        restore: bool,
+    },
+    /// Inspect the cgroup cpusets, which the kernel strips of cores going offline.
+    Cpuset {
+        #[command(subcommand)]
+        command: CpusetCommand,
+    },
+    /// Check kernel hotplug support, sysfs, containers and VMs, SMT, privileges, cgroups and boot
+    /// parameters, and report what may keep `cores` from working. Exits with 1 if a check fails.
+    Doctor,
+    /// Restore the given online cores after a delay. Started in the background by `--for --detach`.
+    #[command(hide = true)]
+    RevertAfter {
+        #[arg(value_parser = duration::parse)]
+        delay: Duration,
+        cores_online: String,
+        /// The ID of the last journal entry before the change.
+        since: usize,
+    },
+}
+
+fn print_cores_updated(cores_updated: usize, format: cpu::Format) {
+    if format != cpu::Format::Text {
         return;
     }
     println!("Core(s) updated: {}", cores_updated);
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Subcommand, Debug)]
+enum ScenarioCommand {
+    /// Execute the steps of a plan (TOML) on schedule, see the README for the format.
+    Run {
+        /// The plan file.
+        plan: PathBuf,
+
+        #[command(flatten)]
+        options: ScenarioOptions,
+    },
+    /// Execute the steps recorded in an event log again, at the times they were executed originally.
+    Replay {
+        /// The event log (NDJSON) written by `cores scenario run --log` or `cores scenario run --json`.
+        #[arg(value_name = "LOG")]
+        events: PathBuf,
+
+        #[command(flatten)]
+        options: ScenarioOptions,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HotplugCommand {
+    /// List the states of the hotplug state machine and the current state, target and injected failure of each core.
+    States,
+    /// Make the next hotplug operation of a core fail at STATE, online or offline. The core is rolled back.
+    Fail {
+        /// The core to inject the failure into.
+        core: usize,
+
+        /// The state to fail at, by number or name (see `cores hotplug states`), or `none` to remove the failure.
+        state: String,
+    },
+    /// Bring a core to STATE, e.g. park it at an intermediate state between offline and online.
+    Target {
+        /// The core to bring to STATE.
+        core: usize,
+
+        /// The state to bring the core to, by number or name (see `cores hotplug states`).
+        state: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PstateCommand {
+    /// Show the driver, its current mode and the modes it can be switched to.
+    Status,
+    /// Switch the driver to MODE, e.g. active | passive | guided (amd-pstate) | off (intel_pstate).
+    Set {
+        /// The mode to switch to.
+        mode: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CpusetCommand {
+    /// List the cgroups whose effective cpus differ from the online cores, and the cpus `cores` saved for them.
+    Audit,
+}
+
+#[derive(ClapArgs, Debug)]
+struct ScenarioOptions {
+    /// What the times of the steps are relative to.
+    #[arg(long, value_enum, default_value = "start")]
+    relative_to: scenario::RelativeTo,
+
+    /// Keep the final state instead of restoring the state from before the scenario.
+    #[arg(long)]
+    keep: bool,
+
+    /// Also write the event log to FILE (NDJSON), e.g. to replay it later.
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// A command to run during the scenario, followed by its arguments. Its output goes to stderr.
+    #[arg(last = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+/// If `cores` was not run with root privileges, we can't modify the state of the CPU cores,
+/// hence exit with an error message.
+fn exit_unless_root(drop_result: &DropPrivilegeResult) {
+    if *drop_result == DropPrivilegeResult::NotRoot {
+        eprintln!("Root privileges are needed to modify the state of the CPU cores.\nTry `sudo cores <arguments>`");
+        std::process::exit(1);
+    }
+}
+
+/// Which cores to render and how, according to `--only` and `--layout`.
+fn view(args: &Args) -> cpu::View {
+    cpu::View { only: args.only, layout: args.layout }
+}
+
+/// The output format of the CPU state according to `--format`, `--json` meaning the legacy JSON layout.
+fn output_format(args: &Args) -> cpu::Format {
+    if args.json {
+        cpu::Format::Legacy
+    } else {
+        args.format.unwrap_or_default()
+    }
+}
+
+/// Refuses a change that takes cores offline from under processes pinned to them, unless `--force` is given.
+/// # Returns
+/// The affected processes, to be listed in the JSON output.
+fn check_pinned_tasks(args: &Args, core_states: &HashMap<usize, bool>) -> Result<Vec<PinnedTask>, Box<dyn Error>> {
+    let pinned_tasks = cpu::pinned::find_affected(core_states)?;
+    if pinned_tasks.is_empty() {
+        return Ok(pinned_tasks);
+    }
+
+    if args.force {
+        eprintln!("Warning: the change affects {} process(es) pinned to the cores going offline:", pinned_tasks.len());
+        cpu::pinned::report(&pinned_tasks);
+        return Ok(pinned_tasks);
+    }
+
+    eprintln!("Refusing to take cores offline, {} process(es) are pinned to them:", pinned_tasks.len());
+    cpu::pinned::report(&pinned_tasks);
+    eprintln!("Use --force to apply the change anyway.");
+    cpu::render_change(&cpu::get_state()?, output_format(args), &view(args), &pinned_tasks, None); // Render the unchanged CPU state
+    std::process::exit(1);
+}
+
+/// The desired state of `cores` taken offline, to check for pinned processes with `check_pinned_tasks()`.
+fn offline_states(cores: HashSet<usize>) -> HashMap<usize, bool> {
+    cores.into_iter().map(|core| (core, false)).collect()
+}
+
+/// Prepares `revert_if_timed()` before a change is applied: with `--for` or `--confirm-within`,
+/// interrupts are caught from now on, so that Ctrl-C during the change still restores the state.
+/// # Returns
+/// The ID of the last journal entry before the change, see `journal::last_id()`.
+fn prepare_timed(args: &Args) -> usize {
+    if args.for_duration.is_some() || args.confirm_within.is_some() {
+        catch_interrupts();
+    }
+    journal::last_id()
+}
+
+/// Restores the state taken before a change according to `--for` or `--confirm-within`, if given.
+/// `since` is the journal entry ID returned by `prepare_timed()`.
+fn revert_if_timed(args: &Args, state_before: &CPUState, since: usize) -> Result<(), Box<dyn Error>> {
+    let cores_updated = if let Some(duration) = args.for_duration {
+        if args.detach {
+            let pid = change::timed::spawn_revert_helper(duration, state_before, since)?;
+            eprintln!(
+                "The previous state (online: {}) is restored in {} by a background process (PID {}).",
+                state_before.online_core_list(),
+                duration::format(duration),
+                pid
+            );
+            return Ok(());
+        }
+        change::timed::revert_after(duration, state_before, since)?
+    } else if let Some(duration) = args.confirm_within {
+        match change::timed::revert_unless_confirmed(duration, state_before, since)? {
+            Some(cores_updated) => cores_updated,
+            None => return Ok(()),
+        }
+    } else {
+        return Ok(());
+    };
+
+    cpu::render(&cpu::get_state()?, output_format(args), &view(args)); // Render the restored CPU state
+    print_cores_updated(cores_updated, output_format(args));
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
     //
     // Don't use root privileges for tasks that don't require it.
     // E.g. `clap` does not require root privileges to parse CLI arguments.
@@ -101,28 +596,424 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     //----------------------------------------------------------------------------------------------
-    // Render the CPU state in JSON or human-friendly text format and exit (if no args or just `-j`)
+    // Print the journal of past changes and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::History) = args.command {
+        journal::render(&journal::read()?, args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print the hotplug state machine and the hotplug state of the cores and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Hotplug { command: HotplugCommand::States }) = args.command {
+        cpu::hotplug::render(&cpu::hotplug::get_states()?, &cpu::hotplug::get_cores()?, args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // List the cpusets that can't use all the online cores and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Cpuset { command: CpusetCommand::Audit }) = args.command {
+        cpu::cpuset::render(&cpu::cpuset::audit()?, args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print the cpufreq settings of the cores and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Freq { min: None, max: None, governor: None, boost: None, .. }) = args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        cpu::freq::render(&cpu::freq::read_all(total_cores), cpu::freq::get_boost(), args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print the energy performance preference or the P-state driver mode and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Epp { value: None, .. }) = args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        cpu::pstate::render_epp(&cpu::freq::read_all(total_cores), args.json);
+        return Ok(());
+    }
+    if let Some(Command::Pstate { command: PstateCommand::Status }) = args.command {
+        cpu::pstate::render_status(cpu::pstate::status().as_ref(), args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print the idle states of the cores and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Idle { disable, enable, restore: false, .. }) = &args.command {
+        if disable.is_empty() && enable.is_empty() {
+            cpu::idle::render(&cpu::idle::read_all(cpu::get_state()?.total_cores), args.json);
+            return Ok(());
+        }
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Diagnose the environment and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Doctor) = args.command {
+        if !doctor::render(&doctor::run(&drop_result), args.json) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print a table of the cores and exit (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::List { columns, no_headings, raw }) = &args.command {
+        cpu::list::render(&cpu::get_state()?, columns, *no_headings, *raw, args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Print CPU hotplug events until interrupted (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Monitor { poll }) = args.command {
+        monitor::run(poll, args.json)?;
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Run the interactive grid until quit (root privileges are only needed to apply changes)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Tui) = args.command {
+        tui::run(&drop_result, args.force)?;
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Watch the CPU state until interrupted (no root privileges needed)
+    //----------------------------------------------------------------------------------------------
+    if args.watch {
+        // --json prints the versioned layout, one line per change
+        let format = if args.json { cpu::Format::Json } else { args.format.unwrap_or_default() };
+        watch::run(args.interval, format, &view(&args))?;
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Render the CPU state in JSON or human-friendly text format and exit (if no args or just
+    // options of the output, e.g. `-j` or `--only offline`)
     //----------------------------------------------------------------------------------------------
     let no_args = env::args().len() == 1;
-    let one_arg = env::args().len() == 2;
-    let just_render = no_args || args.json && one_arg;
+    let no_change = args.command.is_none()
+        && args.core.is_none()
+        && args.state.is_none()
+        && args.set.is_none()
+        && !args.reset
+        && args.custom.is_none()
+        && args.for_duration.is_none()
+        && args.confirm_within.is_none()
+        && !args.force;
+    let just_render = no_args || no_change;
     if just_render {
         let cpu_state: CPUState = cpu::get_state()?;
-        cpu::render(&cpu_state, args.json);
+        cpu::render(&cpu_state, output_format(&args), &view(&args));
         if no_args {
             println!("Run `cores --help` for more information");
         }
         return Ok(());
     }
 
+    //----------------------------------------------------------------------------------------------
+    // Run a command with a temporary core configuration, restore the state, and exit with its code
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Exec { soft, set, custom, command }) = &args.command {
+        if *soft {
+            let exit_code = exec::soft::run(*set, custom.as_ref(), command)?;
+            std::process::exit(exit_code);
+        }
+        exit_unless_root(&drop_result);
+
+        let total_cores = cpu::get_state()?.total_cores;
+        let (cores_online, action) = match (set, custom) {
+            (Some(count), _) => ((0..*count).collect(), format!("exec set {}", count)),
+            (_, Some(cores_online)) => {
+                let cores_online = cores_online.clone();
+                let action = format!("exec custom {}", core_list::format(cores_online.iter().copied()));
+                (cores_online, action)
+            }
+            (None, None) => unreachable!("clap requires either --set or --custom"),
+        };
+        let core_states = change::core_states_from(&cores_online, total_cores);
+        check_pinned_tasks(&args, &core_states)?;
+
+        let exit_code = exec::run(&core_states, &action, command)?;
+        std::process::exit(exit_code);
+    }
+
     //----------------------------------------------------------------------------------------------
     // Check for root access
     //----------------------------------------------------------------------------------------------
-    // If `cores` was not run with root privileges, we can't modify the state of the CPU cores,
-    // hence exit with an error message.
-    if drop_result == DropPrivilegeResult::NotRoot {
-        eprintln!("Root privileges are needed to modify the state of the CPU cores.\nTry `sudo cores <arguments>`");
-        std::process::exit(1);
+    exit_unless_root(&drop_result);
+
+    //----------------------------------------------------------------------------------------------
+    // Run a command with different numbers of cores online, print the results, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Sweep { counts, repeat, csv, command }) = &args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        let counts = if counts.is_empty() {
+            sweep::default_counts(total_cores)
+        } else {
+            counts.clone()
+        };
+        check_pinned_tasks(&args, &offline_states(sweep::cores_taken_offline(&counts, total_cores)))?;
+
+        let sweep = sweep::run(&counts, *repeat as usize, command)?;
+        sweep::render(&sweep, args.json, *csv);
+        if let Some(signum) = sweep.interrupted_by {
+            std::process::exit(128 + signum);
+        }
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Inject random core failures, restore the state, and exit (with the code of the command if any)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Chaos { duration, min_online, interval, seed, command }) = &args.command {
+        // Any hotpluggable core may go offline
+        let hotpluggable = cpu::get_hotpluggable_cores()?;
+        check_pinned_tasks(&args, &offline_states(hotpluggable.into_iter().collect()))?;
+
+        let settings = chaos::Settings {
+            duration: *duration,
+            min_online: *min_online,
+            interval: *interval,
+            seed: seed.unwrap_or_else(|| timestamp::now() ^ u64::from(std::process::id()) << 32),
+            as_json: args.json,
+        };
+        let exit_code = chaos::run(&settings, command)?;
+        std::process::exit(exit_code);
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Execute a scenario from a plan or an event log, restore the state, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Scenario { command }) = &args.command {
+        let (steps, options) = match command {
+            ScenarioCommand::Run { plan, options } => {
+                let content = std::fs::read_to_string(plan)
+                    .map_err(|e| format!("Could not read {}. {}", plan.display(), e))?;
+                let steps = scenario::plan::parse(&content).map_err(|e| format!("{}: {}", plan.display(), e))?;
+                (steps, options)
+            }
+            ScenarioCommand::Replay { events, options } => {
+                let file = File::open(events).map_err(|e| format!("Could not read {}. {}", events.display(), e))?;
+                let steps = scenario::read_log(file).map_err(|e| format!("{}: {}", events.display(), e))?;
+                (steps, options)
+            }
+        };
+
+        let total_cores = cpu::get_state()?.total_cores;
+        check_pinned_tasks(&args, &offline_states(scenario::plan::cores_taken_offline(&steps, total_cores)))?;
+
+        let log = match &options.log {
+            Some(path) => Some(File::create(path).map_err(|e| format!("Could not create {}. {}", path.display(), e))?),
+            None => None,
+        };
+        let settings = scenario::Settings {
+            relative_to: options.relative_to,
+            keep: options.keep,
+            as_json: args.json,
+            log,
+        };
+        let exit_code = scenario::run(&steps, settings, &options.command)?;
+        std::process::exit(exit_code);
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Inject a hotplug failure or move a core to a hotplug state, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Hotplug { command }) = &args.command {
+        let states = cpu::hotplug::get_states()?;
+        match command {
+            HotplugCommand::Fail { core, state } => {
+                let fail = match state.as_str() {
+                    "none" => None,
+                    state => Some(cpu::hotplug::resolve_state(&states, state)?),
+                };
+                let action = format!("hotplug fail {} {}", core, state);
+                let read = || Settings::hotplug_fail(*core);
+                change::apply_settings(&action, None, read, || cpu::hotplug::set_fail(*core, fail).map(|_| 1))?;
+                match fail {
+                    Some(number) => println!(
+                        "The next hotplug operation of core {} fails at state {} ({}), e.g. `cores {} off`",
+                        core,
+                        number,
+                        cpu::hotplug::state_name(&states, number),
+                        core
+                    ),
+                    None => println!("Removed the hotplug failure of core {}", core),
+                }
+            }
+            HotplugCommand::Target { core, state } => {
+                let target = cpu::hotplug::resolve_state(&states, state)?;
+                let action = format!("hotplug target {} {}", core, cpu::hotplug::state_name(&states, target));
+                let cores_updated = change::apply(&action, None, || cpu::hotplug::set_target(*core, target))?;
+                cpu::hotplug::render(&states, &cpu::hotplug::get_cores()?, args.json);
+                print_cores_updated(cores_updated, output_format(&args));
+            }
+            HotplugCommand::States => unreachable!("handled before the check for root access"),
+        }
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Isolate cores from general scheduling or return them, render the state, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Isolate { cores }) = &args.command {
+        let action = format!("isolate {}", core_list::format(cores.iter().copied()));
+        let cores_updated = change::apply_settings(&action, None, Settings::isolated, || cpu::cpuset::partition::isolate(cores))?;
+        cpu::render(&cpu::get_state()?, output_format(&args), &view(&args)); // Render the latest CPU state
+        print_cores_updated(cores_updated, output_format(&args));
+        return Ok(());
+    }
+    if let Some(Command::Unisolate { cores }) = &args.command {
+        let action = match cores {
+            Some(cores) => format!("unisolate {}", core_list::format(cores.iter().copied())),
+            None => "unisolate".to_string(),
+        };
+        let cores_updated =
+            change::apply_settings(&action, None, Settings::isolated, || cpu::cpuset::partition::unisolate(cores.as_ref()))?;
+        cpu::render(&cpu::get_state()?, output_format(&args), &view(&args)); // Render the latest CPU state
+        print_cores_updated(cores_updated, output_format(&args));
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Change the cpufreq settings of the cores, render them, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Freq { custom, min, max, governor, boost }) = &args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        let cores = custom.clone().unwrap_or_else(|| (0..total_cores).collect());
+        let settings = cpu::freq::FreqSettings { governor: governor.clone(), min_khz: *min, max_khz: *max };
+
+        let mut action = format!("freq {}", core_list::format(cores.iter().copied()));
+        for (name, value) in [
+            ("governor", governor.clone()),
+            ("min", min.map(cpu::freq::format_frequency)),
+            ("max", max.map(cpu::freq::format_frequency)),
+            ("boost", boost.clone()),
+        ] {
+            if let Some(value) = value {
+                action.push_str(&format!(" {}={}", name, value));
+            }
+        }
+        let cores_updated = change::apply_settings(&action, None, || Settings::freq(total_cores), || {
+            if let Some(boost) = boost {
+                cpu::freq::set_boost(boost == "on")?;
+            }
+            if settings.is_empty() {
+                return Ok(0);
+            }
+            cpu::freq::set(&cores, &settings)
+        })?;
+        cpu::freq::render(&cpu::freq::read_all(total_cores), cpu::freq::get_boost(), args.json);
+        print_cores_updated(cores_updated, output_format(&args));
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Set the energy performance preference or the P-state driver mode, render it, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Epp { custom, value: Some(value) }) = &args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        let cores = custom.clone().unwrap_or_else(|| (0..total_cores).collect());
+        let action = format!("epp {} {}", core_list::format(cores.iter().copied()), value);
+        let read = || Settings::epp(total_cores);
+        let cores_updated = change::apply_settings(&action, None, read, || cpu::pstate::set_epp(&cores, value))?;
+        cpu::pstate::render_epp(&cpu::freq::read_all(total_cores), args.json);
+        print_cores_updated(cores_updated, output_format(&args));
+        return Ok(());
+    }
+    if let Some(Command::Pstate { command: PstateCommand::Set { mode } }) = &args.command {
+        change::apply_settings(&format!("pstate {}", mode), None, Settings::pstate, || cpu::pstate::set_status(mode))?;
+        cpu::pstate::render_status(cpu::pstate::status().as_ref(), args.json);
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Disable, enable or restore idle states of the cores, render them, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Idle { custom, disable, enable, restore }) = &args.command {
+        let total_cores = cpu::get_state()?.total_cores;
+        let cores = custom.clone().unwrap_or_else(|| (0..total_cores).collect());
+        let cores_list = core_list::format(cores.iter().copied());
+        let read = || Settings::idle(total_cores);
+        let states_updated = if *restore {
+            change::apply_settings("idle restore", None, read, cpu::idle::restore)?
+        } else if !disable.is_empty() {
+            let action = format!("idle {} disable {}", cores_list, disable.join(","));
+            change::apply_settings(&action, None, read, || cpu::idle::set_disabled(&cores, disable, true))?
+        } else {
+            let action = format!("idle {} enable {}", cores_list, enable.join(","));
+            change::apply_settings(&action, None, read, || cpu::idle::set_disabled(&cores, enable, false))?
+        };
+        cpu::idle::render(&cpu::idle::read_all(total_cores), args.json);
+        if !args.json {
+            println!("Idle state(s) updated: {}", states_updated);
+        }
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Restore the given state after a delay (the background process started by `--for --detach`)
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::RevertAfter { delay, cores_online, since }) = &args.command {
+        change::timed::run_revert_helper(*delay, &core_list::parse(cores_online), *since)?;
+        return Ok(());
+    }
+
+    //----------------------------------------------------------------------------------------------
+    // Revert the last change recorded in the journal, render the state, and exit
+    //----------------------------------------------------------------------------------------------
+    if let Some(Command::Undo) = args.command {
+        let entries = journal::read()?;
+        let Some(entry) = journal::last_undoable(&entries) else {
+            eprintln!("Nothing to undo");
+            std::process::exit(1);
+        };
+
+        let cpu_state: CPUState = cpu::get_state()?;
+        let action = format!("undo #{} ({})", entry.id, entry.action);
+
+        // Restore the settings from before an operation that did not take cores online or offline
+        if let Some(settings) = &entry.settings {
+            if settings.after.current(cpu_state.total_cores) != settings.after {
+                eprintln!(
+                    "The settings were changed after operation #{} ({}) outside of `cores`, restoring anyway",
+                    entry.id, entry.action
+                );
+            }
+            let read = || settings.before.current(cpu_state.total_cores);
+            let updated = change::apply_settings(&action, Some(entry.id), read, || settings.before.restore(cpu_state.total_cores))?;
+            cpu::render(&cpu::get_state()?, output_format(&args), &view(&args)); // Render the latest CPU state
+            if output_format(&args) == cpu::Format::Text {
+                println!("Setting(s) updated: {}", updated);
+            }
+            return Ok(());
+        }
+
+        if cpu_state.online_core_list() != entry.online_after {
+            eprintln!(
+                "The cores were changed after operation #{} ({}) outside of `cores`, restoring anyway",
+                entry.id, entry.action
+            );
+        }
+
+        // Restore the cores that were online before the operation
+        let core_states = change::core_states_from(&core_list::parse(&entry.online_before), cpu_state.total_cores);
+
+        let pinned_tasks = check_pinned_tasks(&args, &core_states)?;
+
+        let result = change::apply_transitions(&action, Some(entry.id), || cpu::set_cores_with_outcomes(&core_states))?;
+        cpu::render_change(&cpu::get_state()?, output_format(&args), &view(&args), &pinned_tasks, Some(&result)); // Render the latest CPU state
+        print_cores_updated(result.totals.changed, output_format(&args));
+        return Ok(());
     }
 
     //----------------------------------------------------------------------------------------------
@@ -130,11 +1021,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //----------------------------------------------------------------------------------------------
     if args.reset {
         // println!("Resetting all cores to online...");
-        restore_privileges(); // Restore root privileges
-        let cores_updated = cpu::reset_cores()?;
-        drop_privileges(); // Drop root privileges again
-        cpu::render(&cpu::get_state()?, args.json); // Render the latest CPU state
-        print_cores_updated(cores_updated, args.json);
+        let result = change::apply_transitions("reset", None, cpu::reset_cores_with_outcomes)?;
+        cpu::render_change(&cpu::get_state()?, output_format(&args), &view(&args), &[], Some(&result)); // Render the latest CPU state
+        print_cores_updated(result.totals.changed, output_format(&args));
         return Ok(());
     }
 
@@ -163,11 +1052,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        restore_privileges(); // get root access to set the cores
-        let cores_updated = cpu::set_cores(&core_states)?;
-        drop_privileges(); // drop root access
-        cpu::render(&cpu::get_state()?, args.json); // Render the latest CPU state
-        print_cores_updated(cores_updated, args.json);
+        let pinned_tasks = check_pinned_tasks(&args, &core_states)?;
+
+        let action = format!("set {}", cores_to_set_online);
+        let since = prepare_timed(&args);
+        let result = change::apply_transitions(&action, None, || cpu::set_cores_with_outcomes(&core_states))?;
+        cpu::render_change(&cpu::get_state()?, output_format(&args), &view(&args), &pinned_tasks, Some(&result)); // Render the latest CPU state
+        print_cores_updated(result.totals.changed, output_format(&args));
+        revert_if_timed(&args, &cpu_state, since)?;
         return Ok(());
     }
 
@@ -175,36 +1067,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse a custom core range in CPU list format, apply the settings, render the state, and exit
     //----------------------------------------------------------------------------------------------
 
-    if let Some(custom_cpu_range) = args.custom {
-        let new_core_config = core_list::parse(&custom_cpu_range);
+    if let Some(custom_cpu_range) = &args.custom {
+        let new_core_config = core_list::parse(custom_cpu_range);
 
         let cpu_state: CPUState = cpu::get_state()?;
-
-        // Create a HashMap with the needed state for each core
-        let mut new_core_states: HashMap<usize, bool> =
-            HashMap::with_capacity(cpu_state.total_cores);
-
-        for core in 0..cpu_state.total_cores {
-            if new_core_config.contains(&core) {
-                new_core_states.insert(core, true);
-            } else {
-                new_core_states.insert(core, false);
+        let missing: Vec<usize> = new_core_config.iter().copied().filter(|core| *core >= cpu_state.total_cores).collect();
+        if !missing.is_empty() {
+            eprintln!("Core(s) {} do not exist and are ignored", core_list::format(missing));
+            if let Some(explanation) = cpu::boot_params::get().explain_missing() {
+                eprintln!("{}", explanation);
             }
         }
 
-        restore_privileges(); // Set root access
-        let cores_updated = cpu::set_cores(&new_core_states)?; // Set the cores
-        drop_privileges(); // Drop root access
+        // Create a HashMap with the needed state for each core
+        let new_core_states = change::core_states_from(&new_core_config, cpu_state.total_cores);
+        let pinned_tasks = check_pinned_tasks(&args, &new_core_states)?;
+
+        let action = format!("custom {}", core_list::format(new_core_config));
+        let since = prepare_timed(&args);
+        let result = change::apply_transitions(&action, None, || cpu::set_cores_with_outcomes(&new_core_states))?; // Set the cores
 
-        cpu::render(&cpu::get_state()?, args.json); // Render the latest CPU state
-        print_cores_updated(cores_updated, args.json);
+        cpu::render_change(&cpu::get_state()?, output_format(&args), &view(&args), &pinned_tasks, Some(&result)); // Render the latest CPU state
+        print_cores_updated(result.totals.changed, output_format(&args));
+        revert_if_timed(&args, &cpu_state, since)?;
         return Ok(());
     }
 
     //----------------------------------------------------------------------------------------------
     // Set online/offline an individual core, render the state, and exit
     //----------------------------------------------------------------------------------------------
-    match (args.core, args.state) {
+    match (args.core, args.state.as_deref()) {
         (Some(core), Some(state)) => {
             let cpu_state: CPUState = cpu::get_state()?;
             if core < 1 || core > cpu_state.total_cores - 1 {
@@ -212,6 +1104,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "<CORE_NUMBER> must be greater than 0 and less than {}",
                     cpu_state.total_cores
                 );
+                if let Some(explanation) = cpu::boot_params::get().explain_missing().filter(|_| core > 0) {
+                    eprintln!("{}", explanation);
+                }
                 std::process::exit(1);
             }
 
@@ -220,11 +1115,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 std::process::exit(1);
             }
 
-            restore_privileges(); // get root access to set the core
-            let core_updated = cpu::set_core_state(core, state == "on")?;
-            drop_privileges(); // drop root access
-            cpu::render(&cpu::get_state()?, args.json); // Render the latest CPU state
-            print_cores_updated(core_updated as usize, args.json);
+            let core_states = HashMap::from([(core, state == "on")]);
+            let pinned_tasks = check_pinned_tasks(&args, &core_states)?;
+
+            let action = format!("core {} {}", core, state);
+            let since = prepare_timed(&args);
+            let result = change::apply_transitions(&action, None, || cpu::set_cores_with_outcomes(&core_states))?;
+            cpu::render_change(&cpu::get_state()?, output_format(&args), &view(&args), &pinned_tasks, Some(&result)); // Render the latest CPU state
+            print_cores_updated(result.totals.changed, output_format(&args));
+            revert_if_timed(&args, &cpu_state, since)?;
         }
         (_, _) => {
             eprintln!("Bad syntax. Try `cores --help` for usage information.");
@@ -0,0 +1,220 @@
+use crate::core_list;
+use crate::duration;
+use crate::sys_linux::{catch_interrupts, interrupt_signal, UeventSocket};
+use crate::timestamp;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LINUX_CPU_ONLINE: &str = "/sys/devices/system/cpu/online";
+const LINUX_CPU_PRESENT: &str = "/sys/devices/system/cpu/present";
+
+/// How long to wait for a uevent before checking for interrupts.
+const RECEIVE_TIMEOUT_MS: i32 = 100;
+
+/// How often to check for interrupts while waiting for the next poll.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A CPU hotplug event.
+#[derive(Serialize, Debug)]
+struct Event {
+    /// ISO 8601 UTC time with milliseconds.
+    time: String,
+    core: usize,
+    /// `online`, `offline`, `add` or `remove` (`change` for other uevents of a CPU).
+    action: String,
+    /// The online cores right after the event, in CPU list format.
+    online: String,
+    /// `uevent` or `poll`.
+    source: &'static str,
+    /// The sequence number of the uevent, gaps mean missed events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seqnum: Option<u64>,
+}
+
+/// A uevent of the `cpu` subsystem, e.g. `ACTION=offline` for `DEVPATH=/devices/system/cpu/cpu3`.
+#[derive(Debug, PartialEq)]
+struct CpuUevent {
+    core: usize,
+    action: String,
+    seqnum: Option<u64>,
+}
+
+/// Parses a uevent as sent by the kernel: `<action>@<devpath>` followed by `KEY=value` lines,
+/// all NUL-terminated. Returns `None` for uevents not about a CPU.
+fn parse_uevent(message: &[u8]) -> Option<CpuUevent> {
+    let message = String::from_utf8_lossy(message);
+    let mut fields = message.split('\0');
+    fields.next()?.split_once('@')?; // the header, the same information is in the fields
+    let (mut action, mut devpath, mut subsystem, mut seqnum) = (None, None, None, None);
+    for field in fields {
+        match field.split_once('=') {
+            Some(("ACTION", value)) => action = Some(value),
+            Some(("DEVPATH", value)) => devpath = Some(value),
+            Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+            Some(("SEQNUM", value)) => seqnum = value.parse().ok(),
+            _ => {}
+        }
+    }
+    if subsystem? != "cpu" {
+        return None;
+    }
+    let core = devpath?.strip_prefix("/devices/system/cpu/cpu")?.parse().ok()?;
+    Some(CpuUevent { core, action: action?.to_string(), seqnum })
+}
+
+fn read_cpu_list(path: &str) -> HashSet<usize> {
+    fs::read_to_string(path).map_or_else(|_| HashSet::new(), |content| core_list::parse_or_empty(&content))
+}
+
+/// Compares two samples of the present and online cores, in the order the kernel would send the uevents.
+/// # Returns
+/// The cores that changed and how, e.g. `(3, "offline")`.
+fn diff(
+    (present_before, online_before): &(HashSet<usize>, HashSet<usize>),
+    (present_after, online_after): &(HashSet<usize>, HashSet<usize>),
+) -> Vec<(usize, &'static str)> {
+    let mut cores: Vec<usize> = present_before.union(present_after).copied().collect();
+    cores.sort_unstable();
+    let mut changes = Vec::new();
+    for core in cores {
+        match (present_before.contains(&core), present_after.contains(&core)) {
+            (false, true) => changes.push((core, "add")),
+            (true, false) => {
+                if online_before.contains(&core) {
+                    changes.push((core, "offline"));
+                }
+                changes.push((core, "remove"));
+                continue;
+            }
+            _ => {}
+        }
+        match (online_before.contains(&core), online_after.contains(&core)) {
+            (false, true) => changes.push((core, "online")),
+            (true, false) => changes.push((core, "offline")),
+            _ => {}
+        }
+    }
+    changes
+}
+
+fn print(event: &Event, as_json: bool) -> io::Result<()> {
+    if as_json {
+        println!("{}", serde_json::to_string(event).unwrap_or_default());
+    } else {
+        println!("{}  core {:<4} {:<8} online: {}", event.time, event.core, event.action, event.online);
+    }
+    io::stdout().flush()
+}
+
+fn listen(uevent_socket: &UeventSocket, as_json: bool) -> Result<(), Box<dyn Error>> {
+    while interrupt_signal().is_none() {
+        let Some(message) = uevent_socket.receive(RECEIVE_TIMEOUT_MS)? else {
+            continue;
+        };
+        let Some(uevent) = parse_uevent(&message) else {
+            continue;
+        };
+        let event = Event {
+            time: timestamp::format_millis(timestamp::now_millis()),
+            core: uevent.core,
+            action: uevent.action,
+            online: core_list::format(read_cpu_list(LINUX_CPU_ONLINE)),
+            source: "uevent",
+            seqnum: uevent.seqnum,
+        };
+        print(&event, as_json)?;
+    }
+    Ok(())
+}
+
+fn poll(interval: Duration, as_json: bool) -> Result<(), Box<dyn Error>> {
+    let sample = || (read_cpu_list(LINUX_CPU_PRESENT), read_cpu_list(LINUX_CPU_ONLINE));
+    let mut before = sample();
+    loop {
+        let next_sample = Instant::now() + interval;
+        while interrupt_signal().is_none() && Instant::now() < next_sample {
+            thread::sleep(POLL_INTERVAL.min(next_sample.saturating_duration_since(Instant::now())));
+        }
+        if interrupt_signal().is_some() {
+            break;
+        }
+        let after = sample();
+        for (core, action) in diff(&before, &after) {
+            let event = Event {
+                time: timestamp::format_millis(timestamp::now_millis()),
+                core,
+                action: action.to_string(),
+                online: core_list::format(after.1.iter().copied()),
+                source: "poll",
+                seqnum: None,
+            };
+            print(&event, as_json)?;
+        }
+        before = after;
+    }
+    Ok(())
+}
+
+/// Prints CPU hotplug events as they happen until interrupted (e.g. by Ctrl-C), one line per event
+/// (NDJSON in JSON format). The events are received from the kernel as uevents over netlink, so that
+/// short flaps are not missed. Needs no root privileges.
+/// # Arguments
+/// * `poll_interval` - Poll the online and present cores in sysfs every `poll_interval` instead of
+///   receiving uevents. Also used if the uevent socket can't be opened.
+/// * `as_json` - Whether to print NDJSON or human-readable text.
+pub fn run(poll_interval: Option<Duration>, as_json: bool) -> Result<(), Box<dyn Error>> {
+    catch_interrupts();
+    let online = core_list::format(read_cpu_list(LINUX_CPU_ONLINE));
+
+    let uevent_socket = match poll_interval {
+        Some(_) => None,
+        None => match UeventSocket::open() {
+            Ok(uevent_socket) => Some(uevent_socket),
+            Err(e) => {
+                eprintln!("Could not listen to kernel uevents, polling sysfs instead. {}", e);
+                None
+            }
+        },
+    };
+    match uevent_socket {
+        Some(uevent_socket) => {
+            eprintln!("Listening to CPU hotplug uevents, online: {}. Press Ctrl-C to stop", online);
+            listen(&uevent_socket, as_json)
+        }
+        None => {
+            let interval = poll_interval.unwrap_or(Duration::from_millis(100));
+            eprintln!("Polling sysfs every {}, online: {}. Press Ctrl-C to stop", duration::format(interval), online);
+            poll(interval, as_json)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uevent() {
+        let message = b"offline@/devices/system/cpu/cpu3\0ACTION=offline\0DEVPATH=/devices/system/cpu/cpu3\0\
+SUBSYSTEM=cpu\0SEQNUM=4242\0";
+        assert_eq!(
+            parse_uevent(message),
+            Some(CpuUevent { core: 3, action: "offline".to_string(), seqnum: Some(4242) })
+        );
+
+        let message = b"add@/devices/virtual/net/veth0\0ACTION=add\0DEVPATH=/devices/virtual/net/veth0\0SUBSYSTEM=net\0";
+        assert_eq!(parse_uevent(message), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = (core_list::parse("0-3"), core_list::parse("0-2"));
+        let after = (core_list::parse("0-2,4"), core_list::parse("0,2,4"));
+        assert_eq!(diff(&before, &after), vec![(1, "offline"), (3, "remove"), (4, "add"), (4, "online")]);
+    }
+}
@@ -0,0 +1,250 @@
+pub mod plan;
+
+use crate::change;
+use crate::core_list;
+use crate::cpu;
+use crate::exec::{command_from, exit_code, spawn_as_invoking_user, wait};
+use crate::journal;
+use crate::scenario::plan::{cores_of, Action, Step};
+use crate::sys_linux::{catch_interrupts, interrupt_signal};
+use crate::timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check for interrupts and for the command to exit while waiting for the next step.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the times of the steps are relative to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RelativeTo {
+    /// The start of the scenario. Steps at 0s are executed before the command is started.
+    Start,
+    /// The start of the command.
+    Command,
+}
+
+/// An entry of the event log, written as a line of JSON (NDJSON).
+/// Logs written by `cores scenario run` can be replayed with `cores scenario replay`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Event {
+    /// Wall clock time of the event (UTC).
+    pub timestamp: String,
+    /// Time since the start of the scenario (or of the command).
+    pub elapsed_secs: f64,
+    /// When the step was scheduled, `null` for events that are not steps.
+    pub scheduled_secs: Option<f64>,
+    /// `online`, `offline`, `set`, `custom`, `reset` for steps,
+    /// `start`, `command_start`, `command_exit`, `restore` otherwise.
+    pub action: String,
+    /// The argument of the action, e.g. a CPU list, or the exit code for `command_exit`.
+    pub argument: Option<String>,
+    pub ok: bool,
+    pub error: Option<String>,
+    /// The online cores after the event.
+    pub online: String,
+}
+
+/// Prints the events and writes them to the log file, if any.
+struct EventLog {
+    started: Instant,
+    as_json: bool,
+    file: Option<File>,
+}
+
+impl EventLog {
+    fn record(&mut self, action: &str, argument: Option<String>, scheduled: Option<Duration>, outcome: Result<(), String>) {
+        let event = Event {
+            timestamp: timestamp::format(timestamp::now()),
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+            scheduled_secs: scheduled.map(|at| at.as_secs_f64()),
+            action: action.to_string(),
+            argument,
+            ok: outcome.is_ok(),
+            error: outcome.err(),
+            online: cpu::get_state().map(|state| state.online_core_list()).unwrap_or_default(),
+        };
+
+        let line = serde_json::to_string(&event).unwrap_or_default();
+        if self.as_json {
+            println!("{}", line);
+        } else {
+            let argument = event.argument.as_ref().map_or(String::new(), |argument| format!(" {}", argument));
+            let failure = event.error.as_ref().map_or(String::new(), |e| format!(" FAILED: {}", e));
+            println!(
+                "{} +{:>9.3}s {}{}{} (online: {})",
+                event.timestamp, event.elapsed_secs, event.action, argument, failure, event.online
+            );
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = writeln!(file, "{}", line) {
+                eprintln!("Could not write the event log. {}", e);
+                self.file = None;
+            }
+        }
+    }
+}
+
+/// Executes a single action with root privileges and records it in the journal.
+/// # Returns
+/// The number of cores updated.
+fn execute(action: &Action) -> Result<usize, Box<dyn Error>> {
+    let total_cores = cpu::get_state()?.total_cores;
+
+    let description = match action.argument() {
+        Some(argument) => format!("scenario {} {}", action.name(), argument),
+        None => format!("scenario {}", action.name()),
+    };
+    change::apply(&description, None, || match action {
+        Action::Online(cpu_list) | Action::Offline(cpu_list) => {
+            let is_online = matches!(action, Action::Online(_));
+            let mut cores_updated = 0;
+            for core in cores_of(cpu_list) {
+                if cpu::set_core_state(core, is_online)? {
+                    cores_updated += 1;
+                }
+            }
+            Ok(cores_updated)
+        }
+        Action::Set(count) => {
+            let cores_online: HashSet<usize> = (0..*count).collect();
+            cpu::set_cores(&change::core_states_from(&cores_online, total_cores))
+        }
+        Action::Custom(cpu_list) => {
+            let cores_online = core_list::parse(cpu_list);
+            cpu::set_cores(&change::core_states_from(&cores_online, total_cores))
+        }
+        Action::Reset => cpu::reset_cores(),
+    })
+}
+
+/// Executes a step and logs the outcome.
+fn run_step(log: &mut EventLog, step: &Step) {
+    let outcome = execute(&step.action).map(|_| ()).map_err(|e| e.to_string());
+    log.record(step.action.name(), step.action.argument(), Some(step.at), outcome);
+}
+
+/// Waits until `deadline`. Returns early if `cores` is interrupted or the command exits.
+fn wait_until(deadline: Instant, child: &mut Option<Child>) -> io::Result<Option<ExitStatus>> {
+    while interrupt_signal().is_none() {
+        if let Some(process) = child.as_mut() {
+            if let Some(status) = process.try_wait()? {
+                return Ok(Some(status));
+            }
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+    Ok(None)
+}
+
+/// Settings of a scenario run.
+pub struct Settings {
+    pub relative_to: RelativeTo,
+    /// Keep the final state instead of restoring the state from before the scenario.
+    pub keep: bool,
+    pub as_json: bool,
+    /// Also write the events to this file as NDJSON.
+    pub log: Option<File>,
+}
+
+/// Executes the steps on schedule, optionally while a command runs, logging every event to stdout.
+/// The scenario ends after the last step, or when the command exits or `cores` is interrupted.
+/// The state from before the scenario is restored at the end, unless `keep` is set. The output
+/// of the command goes to stderr to keep stdout for the event log.
+/// # Returns
+/// The exit code of the command if there is one, otherwise `128 + N` if interrupted by signal N or `0`.
+pub fn run(steps: &[Step], settings: Settings, command: &[String]) -> Result<i32, Box<dyn Error>> {
+    let state_before = cpu::get_state()?;
+    let since = journal::last_id();
+
+    // From now on, an interrupt must not terminate `cores` before the previous state is restored
+    catch_interrupts();
+
+    let mut log = EventLog {
+        started: Instant::now(),
+        as_json: settings.as_json,
+        file: settings.log,
+    };
+    log.record("start", None, None, Ok(()));
+
+    let mut pending = steps.iter().peekable();
+    if settings.relative_to == RelativeTo::Start {
+        while let Some(step) = pending.next_if(|step| step.at.is_zero()) {
+            run_step(&mut log, step);
+        }
+    }
+
+    let mut child: Option<Child> = None;
+    if !command.is_empty() {
+        let mut process = command_from(command);
+        process.stdout(io::stderr());
+        child = Some(spawn_as_invoking_user(process, None)?);
+        if settings.relative_to == RelativeTo::Command {
+            log.started = Instant::now();
+        }
+        log.record("command_start", Some(command.join(" ")), None, Ok(()));
+    }
+
+    let mut exit_status = None;
+    for step in pending {
+        exit_status = wait_until(log.started + step.at, &mut child)?;
+        if exit_status.is_some() || interrupt_signal().is_some() {
+            break;
+        }
+        run_step(&mut log, step);
+    }
+
+    // All the steps are done, but the command may still be running
+    if let (Some(process), None) = (child, exit_status) {
+        exit_status = Some(wait(process)?.0);
+    }
+    if let Some(status) = exit_status {
+        log.record("command_exit", Some(exit_code(&status).to_string()), None, Ok(()));
+    }
+
+    if !settings.keep {
+        let cores_online: HashSet<usize> = state_before.online_cores().into_iter().collect();
+        let reason = format!("revert to {} (scenario finished)", core_list::format(cores_online.iter().copied()));
+        let restored = change::restore(&cores_online, &reason, since).map(|_| ()).map_err(|e| e.to_string());
+        log.record("restore", None, None, restored.clone());
+        restored?;
+    }
+
+    Ok(match (exit_status, interrupt_signal()) {
+        (Some(status), _) => exit_code(&status),
+        (None, Some(signum)) => 128 + signum,
+        (None, None) => 0,
+    })
+}
+
+/// Reads the steps to replay from an event log written by `run()`.
+/// Every step is scheduled at the time it was actually executed in the original run.
+pub fn read_log(file: File) -> Result<Vec<Step>, Box<dyn Error>> {
+    let mut steps = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event: Event = serde_json::from_str(&line).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        // Only steps have a schedule, other events are informational
+        if event.scheduled_secs.is_none() {
+            continue;
+        }
+        let action = Action::from_parts(&event.action, event.argument.as_deref()).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        let at = Duration::try_from_secs_f64(event.elapsed_secs).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        steps.push(Step { at, action });
+    }
+    steps.sort_by_key(|step| step.at);
+    Ok(steps)
+}
@@ -0,0 +1,251 @@
+use crate::core_list;
+use crate::duration;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+/// An action of a scenario, with the same semantics as the corresponding `cores` command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Set the cores in the CPU list online, other cores remain unchanged (as `cores N on`).
+    Online(String),
+    /// Set the cores in the CPU list offline, other cores remain unchanged (as `cores N off`).
+    Offline(String),
+    /// Set this number of cores online and all the other cores offline (as `cores --set`).
+    Set(usize),
+    /// Set the cores in the CPU list online and all the other cores offline (as `cores --custom`).
+    Custom(String),
+    /// Set all the cores online (as `cores --reset`).
+    Reset,
+}
+
+impl Action {
+    /// The name of the action as used in plans and event logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Online(_) => "online",
+            Action::Offline(_) => "offline",
+            Action::Set(_) => "set",
+            Action::Custom(_) => "custom",
+            Action::Reset => "reset",
+        }
+    }
+
+    /// The argument of the action as used in event logs, e.g. a CPU list.
+    pub fn argument(&self) -> Option<String> {
+        match self {
+            Action::Online(cpu_list) | Action::Offline(cpu_list) | Action::Custom(cpu_list) => Some(cpu_list.clone()),
+            Action::Set(count) => Some(count.to_string()),
+            Action::Reset => None,
+        }
+    }
+
+    /// Creates an action from its name and argument, the inverse of `name()` and `argument()`.
+    pub fn from_parts(name: &str, argument: Option<&str>) -> Result<Action, String> {
+        let cpu_list = || -> Result<String, String> {
+            let cpu_list = argument.ok_or(format!("Action '{}' requires a CPU list", name))?;
+            core_list::try_parse(cpu_list)?;
+            Ok(cpu_list.to_string())
+        };
+        match name {
+            "online" => Ok(Action::Online(cpu_list()?)),
+            "offline" => Ok(Action::Offline(cpu_list()?)),
+            "custom" => Ok(Action::Custom(cpu_list()?)),
+            "set" => argument
+                .and_then(|count| count.parse().ok())
+                .map(Action::Set)
+                .ok_or(format!("Action 'set' requires a number of cores, got {:?}", argument)),
+            "reset" => Ok(Action::Reset),
+            _ => Err(format!("Unknown action '{}'", name)),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.argument() {
+            Some(argument) => write!(f, "{} {}", self.name(), argument),
+            None => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+/// A scheduled action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// When to execute the action, relative to the start of the scenario (or of the command).
+    pub at: Duration,
+    pub action: Action,
+}
+
+/// A step as written in a plan file: the time and exactly one of the actions.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanStep {
+    at: String,
+    online: Option<String>,
+    offline: Option<String>,
+    set: Option<usize>,
+    custom: Option<String>,
+    reset: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+    #[serde(default)]
+    step: Vec<PlanStep>,
+}
+
+/// Parses a scenario plan in TOML format, e.g.:
+/// ```toml
+/// [[step]]
+/// at = "2s"
+/// offline = "3"
+///
+/// [[step]]
+/// at = "5s"
+/// custom = "0-1"
+///
+/// [[step]]
+/// at = "9s"
+/// reset = true
+/// ```
+/// # Returns
+/// The steps ordered by time.
+pub fn parse(input: &str) -> Result<Vec<Step>, String> {
+    let plan: PlanFile = toml::from_str(input).map_err(|e| e.to_string())?;
+
+    let mut steps = Vec::with_capacity(plan.step.len());
+    for (i, step) in plan.step.into_iter().enumerate() {
+        let at = duration::parse(&step.at).map_err(|e| format!("Step {}: {}", i + 1, e))?;
+
+        let mut actions = Vec::new();
+        if let Some(cpu_list) = step.online {
+            actions.push(("online", Some(cpu_list)));
+        }
+        if let Some(cpu_list) = step.offline {
+            actions.push(("offline", Some(cpu_list)));
+        }
+        if let Some(count) = step.set {
+            actions.push(("set", Some(count.to_string())));
+        }
+        if let Some(cpu_list) = step.custom {
+            actions.push(("custom", Some(cpu_list)));
+        }
+        if step.reset == Some(true) {
+            actions.push(("reset", None));
+        }
+        if actions.len() != 1 {
+            return Err(format!(
+                "Step {}: exactly one of `online`, `offline`, `set`, `custom` or `reset` is required",
+                i + 1
+            ));
+        }
+
+        let (name, argument) = actions.remove(0);
+        let action = Action::from_parts(name, argument.as_deref()).map_err(|e| format!("Step {}: {}", i + 1, e))?;
+        steps.push(Step { at, action });
+    }
+
+    // A stable sort keeps the order of the plan for steps scheduled at the same time
+    steps.sort_by_key(|step| step.at);
+    Ok(steps)
+}
+
+/// Returns the cores of a CPU list validated by `parse()`.
+pub fn cores_of(cpu_list: &str) -> Vec<usize> {
+    let mut cores: Vec<usize> = core_list::parse(cpu_list).into_iter().collect();
+    cores.sort_unstable();
+    cores
+}
+
+/// Returns the cores that any of the steps takes offline, to check for pinned processes up front.
+/// # Arguments
+/// * `total_cores` - The number of cores, see `CPUState`.
+pub fn cores_taken_offline(steps: &[Step], total_cores: usize) -> HashSet<usize> {
+    let mut cores = HashSet::new();
+    for step in steps {
+        match &step.action {
+            Action::Offline(cpu_list) => cores.extend(core_list::parse(cpu_list)),
+            Action::Set(count) => cores.extend(*count..total_cores),
+            Action::Custom(cpu_list) => {
+                let cores_online = core_list::parse(cpu_list);
+                cores.extend((0..total_cores).filter(|core| !cores_online.contains(core)));
+            }
+            Action::Online(_) | Action::Reset => {}
+        }
+    }
+    cores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plan() {
+        let input = r#"
+            [[step]]
+            at = "9s"
+            reset = true
+
+            [[step]]
+            at = "2s"
+            offline = "3"
+
+            [[step]]
+            at = "5s"
+            custom = "0-1"
+
+            [[step]]
+            at = "5s"
+            set = 4
+        "#;
+        let expected = vec![
+            Step { at: Duration::from_secs(2), action: Action::Offline("3".to_string()) },
+            Step { at: Duration::from_secs(5), action: Action::Custom("0-1".to_string()) },
+            Step { at: Duration::from_secs(5), action: Action::Set(4) },
+            Step { at: Duration::from_secs(9), action: Action::Reset },
+        ];
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_step_without_action() {
+        assert!(parse("[[step]]\nat = \"1s\"").is_err());
+    }
+
+    #[test]
+    fn test_step_with_two_actions() {
+        assert!(parse("[[step]]\nat = \"1s\"\nset = 2\nreset = true").is_err());
+    }
+
+    #[test]
+    fn test_invalid_cpu_list() {
+        assert!(parse("[[step]]\nat = \"1s\"\noffline = \"a-b\"").is_err());
+        assert!(parse("[[step]]\nat = \"1s\"\noffline = \"1-2-3\"").is_err());
+    }
+
+    #[test]
+    fn test_cores_taken_offline() {
+        let steps = vec![
+            Step { at: Duration::from_secs(1), action: Action::Offline("7".to_string()) },
+            Step { at: Duration::from_secs(2), action: Action::Set(6) },
+            Step { at: Duration::from_secs(3), action: Action::Custom("0-3,5-7".to_string()) },
+            Step { at: Duration::from_secs(4), action: Action::Online("0-7".to_string()) },
+            Step { at: Duration::from_secs(5), action: Action::Reset },
+        ];
+        assert_eq!(cores_taken_offline(&steps, 8), HashSet::from([4, 6, 7]));
+        assert_eq!(cores_taken_offline(&steps[3..], 8), HashSet::new());
+    }
+
+    #[test]
+    fn test_action_parts_round_trip() {
+        for action in [Action::Online("1-3".to_string()), Action::Set(2), Action::Reset] {
+            let argument = action.argument();
+            assert_eq!(Action::from_parts(action.name(), argument.as_deref()), Ok(action));
+        }
+    }
+}
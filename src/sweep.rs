@@ -0,0 +1,303 @@
+use crate::change;
+use crate::core_list;
+use crate::cpu;
+use crate::exec::{command_from, exit_code, spawn_as_invoking_user, wait};
+use crate::journal;
+use crate::json;
+use crate::sys_linux::{catch_interrupts, interrupt_signal};
+use std::collections::HashSet;
+use std::error::Error;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// A single run of the command.
+#[derive(Debug)]
+pub struct Run {
+    /// Wall time of the run.
+    pub wall_time: Duration,
+    /// Exit code of the command, `128 + N` if it was killed by signal N.
+    pub exit_code: i32,
+}
+
+/// All the runs of the command with a given number of cores online.
+#[derive(Debug)]
+pub struct Step {
+    /// The number of cores online.
+    pub cores: usize,
+    pub runs: Vec<Run>,
+}
+
+impl Step {
+    /// Mean wall time of the runs in seconds.
+    pub fn mean_secs(&self) -> f64 {
+        let total: f64 = self.runs.iter().map(|run| run.wall_time.as_secs_f64()).sum();
+        total / self.runs.len().max(1) as f64
+    }
+
+    /// Shortest wall time of the runs in seconds.
+    pub fn min_secs(&self) -> f64 {
+        self.runs.iter().map(|run| run.wall_time.as_secs_f64()).fold(f64::INFINITY, f64::min)
+    }
+
+    /// Longest wall time of the runs in seconds.
+    pub fn max_secs(&self) -> f64 {
+        self.runs.iter().map(|run| run.wall_time.as_secs_f64()).fold(0.0, f64::max)
+    }
+
+    /// The number of runs that exited with a non-zero exit code.
+    pub fn failures(&self) -> usize {
+        self.runs.iter().filter(|run| run.exit_code != 0).count()
+    }
+
+    /// Speedup relative to the `base` step: the ratio of their mean wall times.
+    /// `None` if the runs of this step took no measurable time.
+    pub fn speedup(&self, base: &Step) -> Option<f64> {
+        let mean_secs = self.mean_secs();
+        (mean_secs > 0.0).then(|| base.mean_secs() / mean_secs)
+    }
+
+    /// Parallel efficiency relative to the `base` step: the speedup divided by the ratio of cores.
+    /// `1.0` means perfect scaling. `None` if the speedup is unknown.
+    pub fn efficiency(&self, base: &Step) -> Option<f64> {
+        Some(self.speedup(base)? / (self.cores as f64 / base.cores as f64))
+    }
+}
+
+/// The outcome of a sweep.
+#[derive(Debug)]
+pub struct Sweep {
+    pub command: String,
+    pub steps: Vec<Step>,
+    /// Set if the sweep was stopped early by this signal.
+    pub interrupted_by: Option<i32>,
+}
+
+/// The default core counts of a sweep: powers of two up to `total_cores`, plus `total_cores` itself.
+pub fn default_counts(total_cores: usize) -> Vec<usize> {
+    let mut counts: Vec<usize> = (0..usize::BITS)
+        .map(|exponent| 1usize << exponent)
+        .take_while(|count| *count < total_cores)
+        .collect();
+    counts.push(total_cores.max(1));
+    counts
+}
+
+/// Returns the cores that the sweep takes offline: all but the smallest number of cores in `counts`.
+pub fn cores_taken_offline(counts: &[usize], total_cores: usize) -> HashSet<usize> {
+    let fewest = counts.iter().map(|count| (*count).clamp(1, total_cores)).min().unwrap_or(total_cores);
+    (fewest..total_cores).collect()
+}
+
+/// Runs `command` `repeat` times with each number of cores in `counts` online, in ascending order
+/// and each number once, measuring the wall time of every run. The state of the cores before the sweep is restored at the end, even if
+/// `cores` is interrupted. The output of the command goes to stderr to keep stdout for the results.
+pub fn run(counts: &[usize], repeat: usize, command: &[String]) -> Result<Sweep, Box<dyn Error>> {
+    let state_before = cpu::get_state()?;
+    let total_cores = state_before.total_cores;
+    let since = journal::last_id();
+
+    let mut counts: Vec<usize> = counts.iter().map(|count| (*count).clamp(1, total_cores)).collect();
+    counts.sort_unstable();
+    counts.dedup();
+
+    // From now on, an interrupt must not terminate `cores` before the previous state is restored
+    catch_interrupts();
+
+    let mut sweep = Sweep {
+        command: command.join(" "),
+        steps: Vec::with_capacity(counts.len()),
+        interrupted_by: None,
+    };
+    let mut result = Ok(());
+
+    'steps: for count in counts {
+        let cores_online: HashSet<usize> = (0..count).collect();
+        let core_states = change::core_states_from(&cores_online, total_cores);
+        if let Err(e) = change::apply(&format!("sweep set {}", count), None, || cpu::set_cores(&core_states)) {
+            result = Err(e);
+            break;
+        }
+
+        let mut step = Step { cores: count, runs: Vec::with_capacity(repeat) };
+        for i in 1..=repeat {
+            let mut child = command_from(command);
+            child.stdout(io::stderr());
+
+            let started = Instant::now();
+            let finished = spawn_as_invoking_user(child, None).and_then(|child| Ok(wait(child)?));
+            let (status, exited) = match finished {
+                Ok(finished) => finished,
+                Err(e) => {
+                    result = Err(e);
+                    break 'steps;
+                }
+            };
+            let run = Run { wall_time: exited - started, exit_code: exit_code(&status) };
+            eprintln!(
+                "[{} core(s)] run {}/{}: {:.3}s, exit code {}",
+                count,
+                i,
+                repeat,
+                run.wall_time.as_secs_f64(),
+                run.exit_code
+            );
+            step.runs.push(run);
+
+            if let Some(signum) = interrupt_signal() {
+                sweep.interrupted_by = Some(signum);
+                sweep.steps.push(step);
+                break 'steps;
+            }
+        }
+        sweep.steps.push(step);
+    }
+
+    let cores_online: HashSet<usize> = state_before.online_cores().into_iter().collect();
+    let reason = format!("revert to {} (sweep finished)", core_list::format(cores_online.iter().copied()));
+    change::restore(&cores_online, &reason, since)?;
+
+    result.map(|_| sweep)
+}
+
+fn render_as_text(sweep: &Sweep) {
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!("SWEEP: {}", sweep.command);
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+    println!(
+        "{:>5}  {:>4}  {:>10}  {:>10}  {:>10}  {:>7}  {:>10}  {:>6}",
+        "CORES", "RUNS", "MEAN (s)", "MIN (s)", "MAX (s)", "SPEEDUP", "EFFICIENCY", "FAILED"
+    );
+    if let Some(base) = sweep.steps.first() {
+        for step in &sweep.steps {
+            println!(
+                "{:>5}  {:>4}  {:>10.3}  {:>10.3}  {:>10.3}  {:>7}  {:>10}  {:>6}",
+                step.cores,
+                step.runs.len(),
+                step.mean_secs(),
+                step.min_secs(),
+                step.max_secs(),
+                step.speedup(base).map_or("-".to_string(), |speedup| format!("{:.2}", speedup)),
+                step.efficiency(base).map_or("-".to_string(), |efficiency| format!("{:.1}%", efficiency * 100.0)),
+                step.failures()
+            );
+        }
+    }
+    println!("―――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――――");
+}
+
+fn render_as_csv(sweep: &Sweep) {
+    println!("cores,runs,mean_secs,min_secs,max_secs,speedup,efficiency,failures");
+    if let Some(base) = sweep.steps.first() {
+        for step in &sweep.steps {
+            println!(
+                "{},{},{:.6},{:.6},{:.6},{},{},{}",
+                step.cores,
+                step.runs.len(),
+                step.mean_secs(),
+                step.min_secs(),
+                step.max_secs(),
+                step.speedup(base).map_or(String::new(), |speedup| format!("{:.4}", speedup)),
+                step.efficiency(base).map_or(String::new(), |efficiency| format!("{:.4}", efficiency)),
+                step.failures()
+            );
+        }
+    }
+}
+
+fn render_as_json(sweep: &Sweep) {
+    let steps = match sweep.steps.first() {
+        Some(base) => sweep
+            .steps
+            .iter()
+            .map(|step| {
+                let runs = step
+                    .runs
+                    .iter()
+                    .map(|run| format!(r#"{{"wall_secs":{:.6},"exit_code":{}}}"#, run.wall_time.as_secs_f64(), run.exit_code))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                format!(
+                    r#"{{"cores":{},"mean_secs":{:.6},"min_secs":{:.6},"max_secs":{:.6},"speedup":{},"efficiency":{},"failures":{},"runs":[{}]}}"#,
+                    step.cores,
+                    step.mean_secs(),
+                    step.min_secs(),
+                    step.max_secs(),
+                    step.speedup(base).map_or("null".to_string(), |speedup| format!("{:.4}", speedup)),
+                    step.efficiency(base).map_or("null".to_string(), |efficiency| format!("{:.4}", efficiency)),
+                    step.failures(),
+                    runs
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(","),
+        None => String::new(),
+    };
+    println!(
+        r#"{{"command":{},"interrupted":{},"steps":[{}]}}"#,
+        json::string(&sweep.command),
+        sweep.interrupted_by.is_some(),
+        steps
+    );
+}
+
+/// Renders the results of a sweep as a table, CSV or JSON.
+/// Speedup and parallel efficiency are relative to the first step of the sweep.
+pub fn render(sweep: &Sweep, as_json: bool, as_csv: bool) {
+    if as_json {
+        render_as_json(sweep);
+    } else if as_csv {
+        render_as_csv(sweep);
+    } else {
+        render_as_text(sweep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(cores: usize, secs: &[u64]) -> Step {
+        Step {
+            cores,
+            runs: secs
+                .iter()
+                .map(|secs| Run { wall_time: Duration::from_secs(*secs), exit_code: 0 })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_default_counts() {
+        assert_eq!(default_counts(1), vec![1]);
+        assert_eq!(default_counts(8), vec![1, 2, 4, 8]);
+        assert_eq!(default_counts(12), vec![1, 2, 4, 8, 12]);
+    }
+
+    #[test]
+    fn test_cores_taken_offline() {
+        assert_eq!(cores_taken_offline(&[4, 2, 8], 8), HashSet::from([2, 3, 4, 5, 6, 7]));
+        assert_eq!(cores_taken_offline(&[0], 4), HashSet::from([1, 2, 3]));
+        assert_eq!(cores_taken_offline(&[8], 8), HashSet::new());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let step = step(2, &[4, 6, 8]);
+        assert_eq!(step.mean_secs(), 6.0);
+        assert_eq!(step.min_secs(), 4.0);
+        assert_eq!(step.max_secs(), 8.0);
+    }
+
+    #[test]
+    fn test_speedup_and_efficiency() {
+        let base = step(1, &[8, 8]);
+        let four_cores = step(4, &[4, 4]);
+        assert_eq!(four_cores.speedup(&base), Some(2.0));
+        assert_eq!(four_cores.efficiency(&base), Some(0.5));
+
+        // Too fast to measure
+        let instant = step(2, &[0, 0]);
+        assert_eq!(instant.speedup(&base), None);
+        assert_eq!(instant.efficiency(&base), None);
+    }
+}
@@ -1,5 +1,10 @@
+use std::collections::HashSet;
 use std::env;
+use std::ffi::{c_char, CStr};
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::string::ToString;
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
 
 const NOT_SUDOER: &str = "not_sudoer";
 
@@ -10,10 +15,147 @@ extern "C" {
     fn seteuid(uid: u32) -> i32;
     /// get real user ID (`man getuid`)
     fn getuid() -> u32;
+    /// set a signal handler (`man 2 signal`)
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    /// run a program in a new session (`man setsid`)
+    fn setsid() -> i32;
+    /// get the password file entry of a user (`man getpwuid_r`)
+    fn getpwuid_r(uid: u32, pwd: *mut Passwd, buf: *mut c_char, buflen: usize, result: *mut *mut Passwd) -> i32;
+    /// get list of groups to which a user belongs (`man getgrouplist`)
+    fn getgrouplist(user: *const c_char, group: u32, groups: *mut u32, ngroups: *mut i32) -> i32;
+    /// set list of supplementary group IDs (`man setgroups`)
+    fn setgroups(size: usize, list: *const u32) -> i32;
+    /// set group identity (`man setgid`)
+    fn setgid(gid: u32) -> i32;
+    /// set user identity (`man setuid`)
+    fn setuid(uid: u32) -> i32;
+    /// send signal to a process (`man 2 kill`)
+    fn kill(pid: i32, sig: i32) -> i32;
+    /// set a thread's CPU affinity mask (`man sched_setaffinity`)
+    fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+    /// get a thread's CPU affinity mask (`man sched_getaffinity`)
+    fn sched_getaffinity(pid: i32, cpusetsize: usize, mask: *mut u64) -> i32;
+    /// create an endpoint for communication (`man 2 socket`)
+    fn socket(domain: i32, kind: i32, protocol: i32) -> i32;
+    /// bind an address to a socket (`man 2 bind`)
+    fn bind(sockfd: i32, addr: *const SockaddrNl, addrlen: u32) -> i32;
+    /// wait for some event on a file descriptor (`man 2 poll`)
+    fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> i32;
+    /// receive a message from a socket (`man 2 recv`)
+    fn recv(sockfd: i32, buf: *mut u8, len: usize, flags: i32) -> isize;
+    /// get the parameters of a terminal (`man tcgetattr`)
+    fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+    /// set the parameters of a terminal (`man tcsetattr`)
+    fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32;
+    /// control a device, here get the size of a terminal (`man 2 ioctl`, `man 2 ioctl_tty`)
+    fn ioctl(fd: i32, request: u64, ...) -> i32;
+    /// read from a file descriptor (`man 2 read`)
+    fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+    /// apply or remove an advisory lock on an open file (`man 2 flock`)
+    fn flock(fd: i32, operation: i32) -> i32;
     // /// get effective user ID (`man geteuid`)
     // fn geteuid() -> u32;
 }
 
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGTERM: i32 = 15;
+
+const AF_NETLINK: i32 = 16;
+const SOCK_DGRAM: i32 = 2;
+const SOCK_CLOEXEC: i32 = 0o2_000_000;
+const NETLINK_KOBJECT_UEVENT: i32 = 15;
+/// The multicast group of the uevents sent by the kernel (udev re-broadcasts them in group 2).
+const UEVENT_KERNEL_GROUP: u32 = 1;
+const POLLIN: i16 = 1;
+const LOCK_EX: i32 = 2;
+
+const STDIN_FILENO: i32 = 0;
+const STDOUT_FILENO: i32 = 1;
+const TCSANOW: i32 = 0;
+const ICANON: u32 = 0o2;
+const ECHO: u32 = 0o10;
+const VTIME: usize = 5;
+const VMIN: usize = 6;
+const TIOCGWINSZ: u64 = 0x5413;
+
+/// `struct termios` (`man termios`), as defined by glibc and musl on Linux.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    c_line: u8,
+    c_cc: [u8; 32],
+    c_ispeed: u32,
+    c_ospeed: u32,
+}
+
+/// `struct winsize` (`man 2 ioctl_tty`)
+#[repr(C)]
+#[derive(Default)]
+struct Winsize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+/// `struct passwd` (`man getpwuid_r`)
+#[repr(C)]
+struct Passwd {
+    pw_name: *mut c_char,
+    pw_passwd: *mut c_char,
+    pw_uid: u32,
+    pw_gid: u32,
+    pw_gecos: *mut c_char,
+    pw_dir: *mut c_char,
+    pw_shell: *mut c_char,
+}
+
+/// `struct sockaddr_nl` (`man 7 netlink`)
+#[repr(C)]
+struct SockaddrNl {
+    nl_family: u16,
+    nl_pad: u16,
+    nl_pid: u32,
+    nl_groups: u32,
+}
+
+/// `struct pollfd` (`man 2 poll`)
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+/// The size of the CPU affinity masks passed to the kernel in 64-bit words, i.e. up to 4096 CPUs.
+const CPU_MASK_WORDS: usize = 64;
+
+/// The number of the last interrupting signal received, `0` if none.
+static INTERRUPT_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// The user ID of the sudoer validated by `drop_privileges()`, `0` if the privileges were not dropped.
+static SUDOER_UID: AtomicU32 = AtomicU32::new(0);
+
+/// The error number of `getpwuid_r()` if the buffer is too small (`ERANGE`).
+const ERANGE: i32 = 34;
+
+/// The error number of `recv()` if the socket receive buffer overflowed (`ENOBUFS`).
+const ENOBUFS: i32 = 105;
+
+/// The identity of the user who ran `cores` with `sudo`.
+#[derive(Debug, Clone)]
+pub struct Sudoer {
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups of the user.
+    pub groups: Vec<u32>,
+}
+
 #[derive(Debug)]
 pub enum DropPrivilegeResult {
     /// The privileges were dropped successfully
@@ -25,6 +167,7 @@ pub enum DropPrivilegeResult {
 }
 
 impl PartialEq for DropPrivilegeResult {
+    #[allow(clippy::match_like_matches_macro)]
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (DropPrivilegeResult::Dropped, DropPrivilegeResult::Dropped) => true,
@@ -72,6 +215,7 @@ pub fn drop_privileges() -> DropPrivilegeResult {
         eprintln!("Failed to drop root privileges");
         std::process::exit(1);
     }
+    SUDOER_UID.store(sudoer_uid, Ordering::SeqCst);
 
     DropPrivilegeResult::Dropped
 }
@@ -84,3 +228,266 @@ pub fn restore_privileges() {
         std::process::exit(1);
     }
 }
+
+/// Returns the name of the user who invoked `cores`.
+/// That is the original user in case of `sudo` (`SUDO_USER`), otherwise the name from `USER`.
+pub fn invoking_user() -> String {
+    env::var("SUDO_USER")
+        .or_else(|_| env::var("USER"))
+        .unwrap_or_else(|_| format!("uid {}", unsafe { getuid() }))
+}
+
+extern "C" fn on_interrupt(signum: i32) {
+    INTERRUPT_SIGNAL.store(signum, Ordering::SeqCst);
+}
+
+/// Catch SIGINT, SIGTERM and SIGHUP instead of being terminated by them.
+/// Should be called before changes that must be reverted even if `cores` is interrupted (e.g. Ctrl-C),
+/// the caller is expected to poll `interrupt_signal()` and clean up.
+pub fn catch_interrupts() {
+    for signum in [SIGHUP, SIGINT, SIGTERM] {
+        unsafe { signal(signum, on_interrupt) };
+    }
+}
+
+/// Returns the number of the interrupting signal received since `catch_interrupts()`, if any.
+pub fn interrupt_signal() -> Option<i32> {
+    match INTERRUPT_SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signum => Some(signum),
+    }
+}
+
+/// Detach the calling process from its controlling terminal by starting a new session.
+/// Meant to be used in a freshly forked child, so that it survives e.g. a closed SSH connection.
+pub fn start_new_session() -> std::io::Result<()> {
+    if unsafe { setsid() } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Looks up the primary group and the name of the user `uid` in the password database.
+fn passwd_entry(uid: u32) -> std::io::Result<(u32, Vec<u8>)> {
+    let mut buffer: Vec<c_char> = vec![0; 1024];
+    loop {
+        let mut entry = std::mem::MaybeUninit::<Passwd>::uninit();
+        let mut result: *mut Passwd = std::ptr::null_mut();
+        let error = unsafe { getpwuid_r(uid, entry.as_mut_ptr(), buffer.as_mut_ptr(), buffer.len(), &mut result) };
+        if error == ERANGE {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        if error != 0 {
+            return Err(std::io::Error::from_raw_os_error(error));
+        }
+        if result.is_null() {
+            return Err(std::io::Error::other(format!("There is no user with the UID {} in the password database", uid)));
+        }
+        // Safety: `getpwuid_r()` filled in the entry, its strings point into `buffer`.
+        let entry = unsafe { entry.assume_init() };
+        let name = unsafe { CStr::from_ptr(entry.pw_name) };
+        return Ok((entry.pw_gid, name.to_bytes_with_nul().to_vec()));
+    }
+}
+
+/// Returns the identity of the original user in case of `sudo`, i.e. the user whose ID was
+/// validated by `drop_privileges()`, with the primary and supplementary groups of that user in
+/// the password and group databases. `None` if the privileges were not dropped, i.e. `cores` was
+/// not run with `sudo` (or by root itself).
+/// Fails if the user is not in the password database, instead of falling back to root.
+pub fn sudoer() -> std::io::Result<Option<Sudoer>> {
+    let uid = SUDOER_UID.load(Ordering::SeqCst);
+    if uid == 0 {
+        return Ok(None);
+    }
+    let (gid, name) = passwd_entry(uid)?;
+
+    // Look up the supplementary groups of the user, growing the buffer until they fit.
+    let mut groups: Vec<u32> = Vec::new();
+    let mut ngroups: i32 = 32;
+    loop {
+        groups.resize(ngroups as usize, 0);
+        let result = unsafe { getgrouplist(name.as_ptr() as *const c_char, gid, groups.as_mut_ptr(), &mut ngroups) };
+        if result >= 0 {
+            groups.truncate(ngroups as usize);
+            break;
+        }
+        if ngroups as usize <= groups.len() {
+            // Not a matter of the buffer size, fall back to the primary group only.
+            groups = vec![gid];
+            break;
+        }
+    }
+
+    Ok(Some(Sudoer { uid, gid, groups }))
+}
+
+/// Permanently switch the calling process to the identity of `sudoer`, including the groups.
+/// Requires root privileges. Meant to be used in a freshly forked child before `exec()`,
+/// as it only makes async-signal-safe system calls.
+pub fn switch_to(sudoer: &Sudoer) -> std::io::Result<()> {
+    // Order matters: the groups can't be changed anymore after giving up the root user ID.
+    let failed = unsafe {
+        setgroups(sudoer.groups.len(), sudoer.groups.as_ptr()) != 0
+            || setgid(sudoer.gid) != 0
+            || setuid(sudoer.uid) != 0
+    };
+    if failed {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Waits until the calling process holds the exclusive lock of `file`. The lock is released when
+/// the file is closed. Only other processes taking the lock are kept out (`man 2 flock`).
+pub fn lock_exclusive(file: &File) -> std::io::Result<()> {
+    loop {
+        if unsafe { flock(file.as_raw_fd(), LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// Send signal `signum` to the process `pid`.
+pub fn send_signal(pid: u32, signum: i32) -> std::io::Result<()> {
+    if unsafe { kill(pid as i32, signum) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Builds a CPU affinity mask for `set_cpu_affinity()` from a set of CPU numbers.
+pub fn cpu_mask(cores: &HashSet<usize>) -> Vec<u64> {
+    let mut mask = vec![0u64; CPU_MASK_WORDS];
+    for core in cores.iter().filter(|core| **core < CPU_MASK_WORDS * 64) {
+        mask[core / 64] |= 1 << (core % 64);
+    }
+    mask
+}
+
+/// Returns the CPUs the calling process is allowed to run on.
+pub fn cpu_affinity() -> std::io::Result<HashSet<usize>> {
+    let mut mask = vec![0u64; CPU_MASK_WORDS];
+    if unsafe { sched_getaffinity(0, CPU_MASK_WORDS * 8, mask.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok((0..CPU_MASK_WORDS * 64)
+        .filter(|core| mask[core / 64] & (1 << (core % 64)) != 0)
+        .collect())
+}
+
+/// Restrict the calling process (and its future children) to the CPUs in `mask`, see `cpu_mask()`.
+/// Only makes an async-signal-safe system call, hence can be used in a freshly forked child.
+pub fn set_cpu_affinity(mask: &[u64]) -> std::io::Result<()> {
+    if unsafe { sched_setaffinity(0, mask.len() * 8, mask.as_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A netlink socket receiving the uevents the kernel sends when devices change, e.g. when a CPU
+/// goes online or offline (`NETLINK_KOBJECT_UEVENT`).
+pub struct UeventSocket(OwnedFd);
+
+impl UeventSocket {
+    pub fn open() -> std::io::Result<UeventSocket> {
+        let fd = unsafe { socket(AF_NETLINK, SOCK_DGRAM | SOCK_CLOEXEC, NETLINK_KOBJECT_UEVENT) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let uevent_socket = UeventSocket(unsafe { OwnedFd::from_raw_fd(fd) });
+        let addr = SockaddrNl { nl_family: AF_NETLINK as u16, nl_pad: 0, nl_pid: 0, nl_groups: UEVENT_KERNEL_GROUP };
+        if unsafe { bind(fd, &addr, std::mem::size_of::<SockaddrNl>() as u32) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(uevent_socket)
+    }
+
+    /// Waits up to `timeout_ms` for a uevent and returns its raw content, `None` if none arrived in
+    /// time or the wait was interrupted by a signal. If uevents were dropped because the receive buffer
+    /// overflowed, a warning is printed and `None` is returned, so that listening goes on.
+    pub fn receive(&self, timeout_ms: i32) -> std::io::Result<Option<Vec<u8>>> {
+        let fd = self.0.as_raw_fd();
+        let mut poll_fd = PollFd { fd, events: POLLIN, revents: 0 };
+        match unsafe { poll(&mut poll_fd, 1, timeout_ms) } {
+            0 => return Ok(None),
+            result if result < 0 => {
+                let e = std::io::Error::last_os_error();
+                return if e.kind() == std::io::ErrorKind::Interrupted { Ok(None) } else { Err(e) };
+            }
+            _ => {}
+        }
+        let mut buffer = vec![0u8; 8192];
+        let length = unsafe { recv(fd, buffer.as_mut_ptr(), buffer.len(), 0) };
+        if length < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.raw_os_error() == Some(ENOBUFS) {
+                eprintln!("Warning: the uevent receive buffer overflowed, some CPU hotplug events were lost");
+                return Ok(None);
+            }
+            return if e.kind() == std::io::ErrorKind::Interrupted { Ok(None) } else { Err(e) };
+        }
+        buffer.truncate(length as usize);
+        Ok(Some(buffer))
+    }
+}
+
+/// The terminal on stdin in raw mode: key presses are read one by one without being echoed.
+/// Signals (e.g. Ctrl-C) are still generated. The previous mode is restored when dropped.
+pub struct RawMode(Termios);
+
+impl RawMode {
+    pub fn enable() -> std::io::Result<RawMode> {
+        let mut termios: Termios = unsafe { std::mem::zeroed() };
+        if unsafe { tcgetattr(STDIN_FILENO, &mut termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let saved = termios;
+        termios.c_lflag &= !(ICANON | ECHO);
+        termios.c_cc[VMIN] = 1;
+        termios.c_cc[VTIME] = 0;
+        if unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &termios) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(RawMode(saved))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &self.0) };
+    }
+}
+
+/// Returns the size of the terminal on stdout as `(rows, columns)`, `None` if stdout is not a terminal.
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut winsize = Winsize::default();
+    if unsafe { ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut winsize as *mut Winsize) } != 0 || winsize.ws_col == 0 {
+        return None;
+    }
+    Some((winsize.ws_row as usize, winsize.ws_col as usize))
+}
+
+/// Waits up to `timeout_ms` for input on stdin and returns what is available, e.g. the bytes of a
+/// key press in raw mode. Empty if nothing arrived in time or the wait was interrupted by a signal.
+pub fn read_input(timeout_ms: i32) -> std::io::Result<Vec<u8>> {
+    let mut poll_fd = PollFd { fd: STDIN_FILENO, events: POLLIN, revents: 0 };
+    let result = unsafe { poll(&mut poll_fd, 1, timeout_ms) };
+    if result <= 0 {
+        let e = std::io::Error::last_os_error();
+        return if result == 0 || e.kind() == std::io::ErrorKind::Interrupted { Ok(Vec::new()) } else { Err(e) };
+    }
+    let mut buffer = vec![0u8; 64];
+    let length = unsafe { read(STDIN_FILENO, buffer.as_mut_ptr(), buffer.len()) };
+    if length < 0 {
+        let e = std::io::Error::last_os_error();
+        return if e.kind() == std::io::ErrorKind::Interrupted { Ok(Vec::new()) } else { Err(e) };
+    }
+    buffer.truncate(length as usize);
+    Ok(buffer)
+}
@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the current time as the number of seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the current time as the number of milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Formats milliseconds since the Unix epoch like `format()`, with milliseconds, e.g. `2024-10-05T14:03:27.042Z`.
+pub fn format_millis(unix_millis: u64) -> String {
+    let seconds = format(unix_millis / 1_000);
+    format!("{}.{:03}Z", seconds.trim_end_matches('Z'), unix_millis % 1_000)
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 UTC timestamp, e.g. `2024-10-05T14:03:27Z`.
+pub fn format(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs_of_day = unix_secs % 86_400;
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's `civil_from_days` algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch() {
+        assert_eq!(format(0), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_leap_day() {
+        assert_eq!(format(951_782_400), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        assert_eq!(format(1_728_137_007), "2024-10-05T14:03:27Z");
+        assert_eq!(format_millis(1_728_137_007_042), "2024-10-05T14:03:27.042Z");
+    }
+}
@@ -0,0 +1,436 @@
+use crate::change;
+use crate::core_list;
+use crate::cpu;
+use crate::cpu::cpu_state::CPUState;
+use crate::cpu::freq::CoreFreq;
+use crate::cpu::pinned::PinnedTask;
+use crate::cpu::topology::{self, CoreTopology};
+use crate::cpu::usage::{self, CpuTimes};
+use crate::sys_linux::{self, catch_interrupts, interrupt_signal, DropPrivilegeResult, RawMode};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+
+/// How often the state, utilization and frequency are refreshed without key presses.
+const REFRESH_MS: i32 = 1000;
+
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+const HOME: &str = "\x1b[H";
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const DEFAULT_COLOR: &str = "\x1b[39m";
+const BOLD: &str = "\x1b[1m";
+const REVERSE: &str = "\x1b[7m";
+const UNDERLINE: &str = "\x1b[4m";
+const RESET: &str = "\x1b[0m";
+
+const KEYS_HELP: &str = "←↑↓→ move  space toggle  v select range  c toggle siblings  a apply  r reset  q quit";
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Enter,
+    Escape,
+    Char(char),
+}
+
+/// Decodes the bytes read from the terminal in raw mode into key presses, including the escape
+/// sequences of the arrow keys (in normal and application cursor mode) and of Page Up/Down.
+fn parse_keys(input: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < input.len() {
+        let rest = &input[i..];
+        let (key, length) = match rest {
+            [0x1b, b'[' | b'O', b'A', ..] => (Some(Key::Up), 3),
+            [0x1b, b'[' | b'O', b'B', ..] => (Some(Key::Down), 3),
+            [0x1b, b'[' | b'O', b'C', ..] => (Some(Key::Right), 3),
+            [0x1b, b'[' | b'O', b'D', ..] => (Some(Key::Left), 3),
+            [0x1b, b'[', b'5', b'~', ..] => (Some(Key::PageUp), 4),
+            [0x1b, b'[', b'6', b'~', ..] => (Some(Key::PageDown), 4),
+            [0x1b, b'[', ..] => (None, rest.iter().position(|b| b.is_ascii_alphabetic() || *b == b'~').map_or(rest.len(), |end| end + 1)),
+            [0x1b, ..] => (Some(Key::Escape), 1),
+            [b'\r' | b'\n', ..] => (Some(Key::Enter), 1),
+            [byte, ..] if byte.is_ascii() => (Some(Key::Char(*byte as char)), 1),
+            _ => (None, 1), // part of a multi-byte character
+        };
+        keys.extend(key);
+        i += length;
+    }
+    keys
+}
+
+/// The interactive view of the cores, and the plan of changes being prepared.
+struct App {
+    state: CPUState,
+    hotpluggable: HashSet<usize>,
+    /// The topology of the cores, offline cores keeping the one they had when last seen online.
+    topologies: BTreeMap<usize, CoreTopology>,
+    /// The packages with their physical cores, each a list of SMT siblings, see `topology::group()`.
+    groups: Vec<(Option<usize>, Vec<Vec<usize>>)>,
+    /// The physical cores of all the packages, as displayed.
+    rows: Vec<Vec<usize>>,
+    /// The row and the column of the core under the cursor.
+    cursor: (usize, usize),
+    /// The core where the range selection started, if selecting.
+    anchor: Option<usize>,
+    /// The cores to change and their planned state.
+    plan: HashMap<usize, bool>,
+    /// The processes pinned to cores the plan takes offline.
+    pinned_tasks: Vec<PinnedTask>,
+    cpu_times: CpuTimes,
+    utilization: HashMap<usize, f64>,
+    frequencies: BTreeMap<usize, CoreFreq>,
+    message: String,
+}
+
+impl App {
+    fn new() -> Result<App, Box<dyn Error>> {
+        let mut app = App {
+            state: cpu::get_state()?,
+            hotpluggable: HashSet::new(),
+            topologies: BTreeMap::new(),
+            groups: Vec::new(),
+            rows: Vec::new(),
+            cursor: (0, 0),
+            anchor: None,
+            plan: HashMap::new(),
+            pinned_tasks: Vec::new(),
+            cpu_times: usage::sample(),
+            utilization: HashMap::new(),
+            frequencies: BTreeMap::new(),
+            message: String::new(),
+        };
+        app.refresh()?;
+        Ok(app)
+    }
+
+    /// Reads the state, topology, utilization and frequency of the cores again, keeping the cursor on the same core.
+    fn refresh(&mut self) -> Result<(), Box<dyn Error>> {
+        let cursor_core = self.cursor_core();
+        self.state = cpu::get_state()?;
+        self.hotpluggable = cpu::get_hotpluggable_cores()?.into_iter().collect();
+        let mut topologies = topology::get_all(self.state.total_cores);
+        topology::fill_unknown(&mut topologies, &self.topologies);
+        self.groups = topology::group(&topologies);
+        self.topologies = topologies;
+        self.rows = self.groups.iter().flat_map(|(_, physical_cores)| physical_cores.iter().cloned()).collect();
+
+        let cpu_times = usage::sample();
+        self.utilization = usage::utilization(&self.cpu_times, &cpu_times);
+        self.cpu_times = cpu_times;
+        self.frequencies = cpu::freq::read_all(self.state.total_cores);
+
+        // Forget planned changes that happened meanwhile.
+        let state = &self.state;
+        self.plan.retain(|core, is_online| state.ordered_core_states.get(*core) != Some(is_online));
+        self.cursor = cursor_core.and_then(|core| self.position_of(core)).unwrap_or((0, 0));
+        Ok(())
+    }
+
+    fn position_of(&self, core: usize) -> Option<(usize, usize)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .find_map(|(row, cores)| Some((row, cores.iter().position(|c| *c == core)?)))
+    }
+
+    fn cursor_core(&self) -> Option<usize> {
+        self.rows.get(self.cursor.0)?.get(self.cursor.1).copied()
+    }
+
+    fn is_online(&self, core: usize) -> bool {
+        self.state.ordered_core_states.get(core).copied().unwrap_or_default()
+    }
+
+    fn planned(&self, core: usize) -> bool {
+        self.plan.get(&core).copied().unwrap_or_else(|| self.is_online(core))
+    }
+
+    /// The cores between the anchor and the cursor in display order, or the core under the cursor.
+    fn selection(&self) -> Vec<usize> {
+        let order: Vec<usize> = self.rows.iter().flatten().copied().collect();
+        let Some(cursor_core) = self.cursor_core() else {
+            return Vec::new();
+        };
+        let Some(anchor) = self.anchor else {
+            return vec![cursor_core];
+        };
+        let position = |core| order.iter().position(|c| *c == core).unwrap_or_default();
+        let (from, to) = (position(anchor).min(position(cursor_core)), position(anchor).max(position(cursor_core)));
+        order[from..=to].to_vec()
+    }
+
+    /// Plans the state of `cores`. Core 0 and the cores that can't be taken offline are skipped.
+    fn plan_state(&mut self, cores: &[usize], is_online: bool) {
+        let (changeable, fixed): (Vec<usize>, Vec<usize>) =
+            cores.iter().partition(|core| **core != 0 && self.hotpluggable.contains(core));
+        for core in changeable {
+            if self.is_online(core) == is_online {
+                self.plan.remove(&core);
+            } else {
+                self.plan.insert(core, is_online);
+            }
+        }
+        self.message = if fixed.is_empty() {
+            String::new()
+        } else {
+            format!("Core(s) {} can't be changed", core_list::format(fixed))
+        };
+        self.pinned_tasks = cpu::pinned::find_affected(&self.plan).unwrap_or_default();
+    }
+
+    fn move_cursor(&mut self, rows: isize, columns: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let row = (self.cursor.0 as isize + rows).clamp(0, self.rows.len() as isize - 1) as usize;
+        let column = (self.cursor.1 as isize + columns).clamp(0, self.rows[row].len() as isize - 1) as usize;
+        self.cursor = (row, column);
+    }
+
+    /// Applies the plan with root privileges, see `change::apply()`.
+    fn apply(&mut self, drop_result: &DropPrivilegeResult, force: bool) -> Result<(), Box<dyn Error>> {
+        if self.plan.is_empty() {
+            self.message = "Nothing to apply, toggle cores with space first".to_string();
+            return Ok(());
+        }
+        if *drop_result == DropPrivilegeResult::NotRoot {
+            self.message = "Root privileges are needed to apply the plan. Try `sudo cores tui`".to_string();
+            return Ok(());
+        }
+        if !self.pinned_tasks.is_empty() && !force {
+            self.message = format!(
+                "Refusing to take cores offline, {} process(es) are pinned to them. Try `cores tui --force`",
+                self.pinned_tasks.len()
+            );
+            return Ok(());
+        }
+        let action = format!("tui {}", describe_plan(&self.plan));
+        let plan = self.plan.clone();
+        self.message = match change::apply(&action, None, || cpu::set_cores(&plan)) {
+            Ok(cores_updated) => format!("Core(s) updated: {}", cores_updated),
+            Err(e) => format!("Could not apply the plan. {}", e),
+        };
+        self.plan.clear();
+        self.pinned_tasks.clear();
+        self.anchor = None;
+        self.refresh()
+    }
+
+    /// Handles a key press.
+    /// # Returns
+    /// Whether to quit.
+    fn handle(&mut self, key: Key, drop_result: &DropPrivilegeResult, force: bool) -> Result<bool, Box<dyn Error>> {
+        match key {
+            Key::Up | Key::Char('k') => self.move_cursor(-1, 0),
+            Key::Down | Key::Char('j') => self.move_cursor(1, 0),
+            Key::Left | Key::Char('h') => self.move_cursor(0, -1),
+            Key::Right | Key::Char('l') => self.move_cursor(0, 1),
+            Key::PageUp => self.move_cursor(-10, 0),
+            Key::PageDown => self.move_cursor(10, 0),
+            Key::Char(' ') => {
+                if let Some(core) = self.cursor_core() {
+                    let is_online = !self.planned(core);
+                    self.plan_state(&self.selection(), is_online);
+                    self.anchor = None;
+                }
+            }
+            Key::Char('c') => {
+                if let (Some(core), Some(siblings)) = (self.cursor_core(), self.rows.get(self.cursor.0).cloned()) {
+                    let is_online = !self.planned(core);
+                    self.plan_state(&siblings, is_online);
+                }
+            }
+            Key::Char('v') => self.anchor = if self.anchor.is_some() { None } else { self.cursor_core() },
+            Key::Char('r') => {
+                self.plan.clear();
+                self.pinned_tasks.clear();
+                self.anchor = None;
+                self.message = String::new();
+            }
+            Key::Char('a') | Key::Enter => self.apply(drop_result, force)?,
+            Key::Escape if self.anchor.is_some() => self.anchor = None,
+            Key::Char('q') | Key::Escape => return Ok(true),
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn cell(&self, core: usize, selection: &HashSet<usize>) -> String {
+        let is_online = self.is_online(core);
+        let glyph = if is_online { format!("{}●{}", GREEN, DEFAULT_COLOR) } else { format!("{}○{}", RED, DEFAULT_COLOR) };
+        let planned = self.planned(core);
+        let change = if planned != is_online {
+            format!("{}→{}{}", YELLOW, if planned { "●" } else { "○" }, DEFAULT_COLOR)
+        } else {
+            "  ".to_string()
+        };
+        let utilization = self.utilization.get(&core).map_or("   -".to_string(), |percent| format!("{:>3.0}%", percent));
+        let frequency = self
+            .frequencies
+            .get(&core)
+            .and_then(|core_freq| core_freq.cur_khz)
+            .map_or("-".to_string(), cpu::freq::format_frequency);
+        let text = format!("{:>4} {}{} {} {:>8}", core, glyph, change, utilization, frequency);
+
+        let mut style = String::new();
+        if self.cursor_core() == Some(core) {
+            style.push_str(REVERSE);
+        }
+        if self.anchor.is_some() && selection.contains(&core) {
+            style.push_str(UNDERLINE);
+        }
+        if style.is_empty() {
+            text
+        } else {
+            format!("{}{}{}", style, text, RESET)
+        }
+    }
+
+    /// Draws the whole screen, scrolled so that the cursor is visible.
+    fn draw(&self) -> io::Result<()> {
+        let (height, _) = sys_linux::terminal_size().unwrap_or((24, 80));
+        let selection: HashSet<usize> = self.selection().into_iter().collect();
+
+        let mut lines = Vec::new();
+        let mut cursor_line = 0;
+        let mut row = 0;
+        for (package, physical_cores) in &self.groups {
+            lines.push(match package {
+                Some(package) => format!("{}Package {}{}", BOLD, package, RESET),
+                None => format!("{}Unknown topology (offline){}", BOLD, RESET),
+            });
+            for cores in physical_cores {
+                if row == self.cursor.0 {
+                    cursor_line = lines.len();
+                }
+                let cells: Vec<String> = cores.iter().map(|core| self.cell(*core, &selection)).collect();
+                lines.push(format!("  {}", cells.join("   ")));
+                row += 1;
+            }
+        }
+
+        let mut footer = vec![match self.plan.is_empty() {
+            true => "Plan: no changes".to_string(),
+            false => format!("Plan: {}  (a: apply, r: reset)", describe_plan(&self.plan)),
+        }];
+        if !self.pinned_tasks.is_empty() {
+            let names: Vec<String> = self.pinned_tasks.iter().take(3).map(|task| format!("{} ({})", task.name, task.pid)).collect();
+            footer.push(format!(
+                "{}Pinned to cores going offline: {}{}{}",
+                YELLOW,
+                names.join(", "),
+                if self.pinned_tasks.len() > 3 { ", ..." } else { "" },
+                RESET
+            ));
+        }
+        footer.push(self.message.clone());
+
+        let header = [
+            format!(
+                "{}cores tui{}  online: {} ({}/{})",
+                BOLD,
+                RESET,
+                self.state.online_core_list(),
+                self.state.cores_online,
+                self.state.total_cores
+            ),
+            KEYS_HELP.to_string(),
+            String::new(),
+        ];
+        let visible = height.saturating_sub(header.len() + footer.len() + 1).max(1);
+        let scroll = (cursor_line + 1).saturating_sub(visible);
+
+        let mut frame = String::from(HOME);
+        let body = lines.iter().skip(scroll).take(visible);
+        for line in header.iter().chain(body).chain(std::iter::once(&String::new())).chain(footer.iter()) {
+            frame.push_str(line);
+            frame.push_str(CLEAR_LINE);
+            frame.push('\n');
+        }
+        frame.push_str(CLEAR_BELOW);
+        let mut stdout = io::stdout();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Describes a plan, e.g. `offline 3,5 online 7`.
+fn describe_plan(plan: &HashMap<usize, bool>) -> String {
+    let cores = |is_online: bool| core_list::format(plan.iter().filter(|(_, state)| **state == is_online).map(|(core, _)| *core));
+    let mut parts = Vec::new();
+    for (name, is_online) in [("offline", false), ("online", true)] {
+        let cores = cores(is_online);
+        if !cores.is_empty() {
+            parts.push(format!("{} {}", name, cores));
+        }
+    }
+    parts.join(" ")
+}
+
+/// Shows the cores in a full-screen grid grouped by package and physical core, with their state,
+/// utilization and frequency, and lets the user plan changes with the keyboard and apply them.
+/// Applying requires root privileges, which are only used for the change itself.
+/// # Arguments
+/// * `drop_result` - The result of `sys_linux::drop_privileges()` at startup.
+/// * `force` - Whether to apply plans that affect processes pinned to cores going offline.
+pub fn run(drop_result: &DropPrivilegeResult, force: bool) -> Result<(), Box<dyn Error>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(Box::from("`cores tui` needs a terminal"));
+    }
+    let mut app = App::new()?;
+    catch_interrupts();
+    let raw_mode = RawMode::enable()?;
+    print!("{}", ENTER_SCREEN);
+
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        while interrupt_signal().is_none() {
+            app.draw()?;
+            let input = sys_linux::read_input(REFRESH_MS)?;
+            if input.is_empty() {
+                app.refresh()?;
+                continue;
+            }
+            for key in parse_keys(&input) {
+                if app.handle(key, drop_result, force)? {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    print!("{}", LEAVE_SCREEN);
+    io::stdout().flush()?;
+    drop(raw_mode);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"\x1b[A\x1bOBq"), vec![Key::Up, Key::Down, Key::Char('q')]);
+        assert_eq!(parse_keys(b"\x1b[6~ \r"), vec![Key::PageDown, Key::Char(' '), Key::Enter]);
+        assert_eq!(parse_keys(b"\x1b"), vec![Key::Escape]);
+        assert_eq!(parse_keys(b"\x1b[1;5Cv"), vec![Key::Char('v')]); // Ctrl-Right is ignored
+    }
+
+    #[test]
+    fn test_describe_plan() {
+        let plan = HashMap::from([(3, false), (5, false), (7, true)]);
+        assert_eq!(describe_plan(&plan), "offline 3,5 online 7");
+    }
+}
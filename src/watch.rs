@@ -0,0 +1,120 @@
+use crate::core_list;
+use crate::cpu;
+use crate::duration;
+use crate::sys_linux::{catch_interrupts, interrupt_signal};
+use crate::timestamp;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check for interrupts while waiting for the next refresh.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// The transitions of the cores between refreshes.
+#[derive(Default)]
+struct Transitions {
+    /// The state of each core at the last refresh, `true` for online.
+    states: Vec<bool>,
+    /// The time of the last transition of each core seen while watching.
+    since: HashMap<usize, u64>,
+    /// The cores whose state changed at the last refresh.
+    changed: HashSet<usize>,
+}
+
+impl Transitions {
+    /// Compares `states` with the previous refresh. Cores that appear are not counted as changed
+    /// on the first refresh, as there is nothing to compare with.
+    fn update(&mut self, states: &[bool], now: u64) {
+        let is_first = self.states.is_empty();
+        self.changed = states
+            .iter()
+            .enumerate()
+            .filter(|(core, is_online)| !is_first && self.states.get(*core) != Some(*is_online))
+            .map(|(core, _)| core)
+            .collect();
+        for core in &self.changed {
+            self.since.insert(*core, now);
+        }
+        self.states = states.to_vec();
+    }
+
+    /// Highlights the cores that changed at the last refresh and notes when each core last changed.
+    fn marks(&self) -> cpu::Marks {
+        cpu::Marks {
+            highlighted: self.changed.clone(),
+            notes: self.since.iter().map(|(core, since)| (*core, format!("since {}", timestamp::format(*since)))).collect(),
+        }
+    }
+}
+
+/// Redraws the state of the cores in text format every `interval` until interrupted (e.g. by Ctrl-C),
+/// highlighting the cores that changed since the previous refresh and showing when each core last
+/// changed. In the other formats, the state is printed whenever it changes, starting with the current state,
+/// e.g. one line of JSON per change (NDJSON). Needs no root privileges.
+/// # Arguments
+/// * `format` - The output format, see `cpu::Format`.
+/// * `view` - Which cores to list and how, as for `cpu::render()`.
+pub fn run(interval: Duration, format: cpu::Format, view: &cpu::View) -> Result<(), Box<dyn Error>> {
+    catch_interrupts();
+    let is_terminal = io::stdout().is_terminal();
+    let mut transitions = Transitions::default();
+
+    while interrupt_signal().is_none() {
+        let state = cpu::get_state()?;
+        let is_first = transitions.states.is_empty();
+        transitions.update(&state.ordered_core_states, timestamp::now());
+
+        if format == cpu::Format::Text {
+            if is_terminal {
+                print!("{}", CLEAR_SCREEN);
+            }
+            print!("Every {}: {}", duration::format(interval), timestamp::format(timestamp::now()));
+            if !transitions.changed.is_empty() {
+                print!(", changed: {}", core_list::format(transitions.changed.iter().copied()));
+            }
+            println!();
+            cpu::render_marked(&state, format, view, &transitions.marks());
+            if is_terminal {
+                println!("Press Ctrl-C to stop watching");
+            }
+        } else if is_first || !transitions.changed.is_empty() {
+            cpu::render(&state, format, view);
+        }
+        io::stdout().flush()?;
+
+        let next_refresh = Instant::now() + interval;
+        while interrupt_signal().is_none() && Instant::now() < next_refresh {
+            thread::sleep(POLL_INTERVAL.min(next_refresh.saturating_duration_since(Instant::now())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transitions() {
+        let mut transitions = Transitions::default();
+        transitions.update(&[true, true, false], 100);
+        assert!(transitions.changed.is_empty());
+        assert!(transitions.since.is_empty());
+
+        transitions.update(&[true, false, true], 101);
+        assert_eq!(transitions.changed, HashSet::from([1, 2]));
+        assert_eq!(transitions.since, HashMap::from([(1, 101), (2, 101)]));
+
+        transitions.update(&[true, false, true, true], 102);
+        assert_eq!(transitions.changed, HashSet::from([3])); // core 3 appeared
+        assert_eq!(transitions.since.get(&1), Some(&101));
+
+        let marks = transitions.marks();
+        assert_eq!(marks.highlighted, HashSet::from([3]));
+        assert_eq!(marks.notes.get(&1), Some(&format!("since {}", timestamp::format(101))));
+    }
+}